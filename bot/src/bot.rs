@@ -0,0 +1,240 @@
+use rand::{seq::SliceRandom, Rng};
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use woods_common::{
+    Credentials, Direction, EnteredView, MoveInput, MoveUpdate, PlayerId, Position, Snapshot,
+    Speed, Welcome,
+};
+
+use crate::connection::Connection;
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::North,
+    Direction::South,
+    Direction::East,
+    Direction::West,
+];
+
+// How likely a wandering bot is to turn instead of carrying on
+const TURN_CHANCE: f64 = 0.25;
+
+#[derive(Clone, Debug)]
+pub enum Behavior {
+    Wander,
+    Idle,
+    // Walks towards the named player while it is in view, and wanders otherwise
+    Follow(String),
+}
+
+impl FromStr for Behavior {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("follow", name)) => Ok(Behavior::Follow(name.to_lowercase())),
+            None if s == "wander" => Ok(Behavior::Wander),
+            None if s == "idle" => Ok(Behavior::Idle),
+            _ => Err("expected wander, idle or follow:<NAME>".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Behavior {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Behavior::Wander => write!(f, "wander"),
+            Behavior::Idle => write!(f, "idle"),
+            Behavior::Follow(name) => write!(f, "follow:{}", name),
+        }
+    }
+}
+
+pub struct Bot {
+    pub credentials: Credentials,
+    pub connection: Option<Connection>,
+    pub player: Option<Player>,
+}
+
+// Set once the server welcomes the bot
+pub struct Player {
+    pub id: PlayerId,
+    position: Position,
+    direction: Direction,
+    sequence: u32,
+    // When each MoveInput not yet echoed back in a MoveUpdate was sent
+    pending: HashMap<u32, Instant>,
+    // Other players in view, for following
+    in_view: HashMap<PlayerId, (String, Position)>,
+    next_step: Instant,
+}
+
+impl Bot {
+    pub fn new(credentials: Credentials) -> Self {
+        Bot {
+            credentials,
+            connection: None,
+            player: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.credentials.username()
+    }
+
+    pub fn welcome(&mut self, welcome: &Welcome, now: Instant) {
+        self.player = Some(Player {
+            id: welcome.player_id,
+            position: welcome.position,
+            direction: welcome.direction,
+            sequence: 0,
+            pending: HashMap::new(),
+            in_view: HashMap::new(),
+            next_step: now,
+        });
+    }
+
+    // Returns the round trip time of every input of ours the snapshot acknowledges
+    pub fn apply(&mut self, snapshot: &Snapshot, now: Instant) -> Vec<Duration> {
+        let player = match &mut self.player {
+            Some(player) => player,
+            None => return Vec::new(),
+        };
+        let mut latencies = Vec::new();
+
+        for EnteredView {
+            player_id,
+            name,
+            position,
+            ..
+        } in &snapshot.entered
+        {
+            player
+                .in_view
+                .insert(*player_id, (name.to_lowercase(), *position));
+        }
+
+        for &MoveUpdate {
+            player_id,
+            direction,
+            position,
+            sequence,
+            ..
+        } in &snapshot.moves
+        {
+            if player_id != player.id {
+                if let Some((_, seen)) = player.in_view.get_mut(&player_id) {
+                    *seen = position;
+                }
+                continue;
+            }
+
+            if let Some(sent) = player.pending.remove(&sequence) {
+                latencies.push(now - sent);
+            }
+            // Inputs the server skipped will never be echoed
+            player.pending.retain(|pending, _| *pending > sequence);
+            // Only the latest input says where the bot really ended up
+            if sequence == player.sequence {
+                player.position = position;
+                player.direction = direction;
+            }
+        }
+
+        for player_id in &snapshot.left {
+            player.in_view.remove(player_id);
+        }
+
+        latencies
+    }
+
+    pub fn forget(&mut self, player_id: PlayerId) {
+        if let Some(player) = &mut self.player {
+            player.in_view.remove(&player_id);
+        }
+    }
+
+    // The input to send now, if it is time for this bot to do something
+    pub fn step(
+        &mut self,
+        behavior: &Behavior,
+        interval: Duration,
+        now: Instant,
+        rng: &mut impl Rng,
+    ) -> Option<MoveInput> {
+        let player = self.player.as_mut()?;
+        if now < player.next_step {
+            return None;
+        }
+        player.next_step = now + interval;
+
+        let direction = match behavior {
+            Behavior::Idle => None,
+            Behavior::Wander => Some(wander(player.direction, rng)),
+            Behavior::Follow(name) => {
+                let target = player
+                    .in_view
+                    .values()
+                    .find(|(other, _)| other == name)
+                    .map(|(_, position)| *position);
+                match target {
+                    Some(target) => towards(player.position, target),
+                    None => Some(wander(player.direction, rng)),
+                }
+            }
+        }?;
+
+        // Facing a new direction only turns; the server moves players that keep their heading
+        if direction == player.direction {
+            player.position = player.position.step(direction).unwrap_or(player.position);
+        }
+        player.direction = direction;
+        player.sequence = player.sequence.wrapping_add(1);
+        player.pending.insert(player.sequence, now);
+
+        Some(MoveInput {
+            sequence: player.sequence,
+            direction,
+            position: player.position,
+            // The server won't take steps sooner than their speed allows
+            speed: if interval < Speed::Walk.step_duration() {
+                Speed::Run
+            } else {
+                Speed::Walk
+            },
+        })
+    }
+}
+
+fn wander(direction: Direction, rng: &mut impl Rng) -> Direction {
+    if rng.gen_bool(TURN_CHANCE) {
+        *DIRECTIONS.choose(rng).unwrap()
+    } else {
+        direction
+    }
+}
+
+// A step along whichever axis the target is furthest away on, or None when already next to it
+fn towards(from: Position, to: Position) -> Option<Direction> {
+    let dx = i32::from(to.x) - i32::from(from.x);
+    let dy = i32::from(to.y) - i32::from(from.y);
+    if dx.abs() + dy.abs() <= 1 {
+        return None;
+    }
+
+    Some(if dx.abs() >= dy.abs() {
+        if dx > 0 {
+            Direction::East
+        } else {
+            Direction::West
+        }
+    } else if dy > 0 {
+        Direction::North
+    } else {
+        Direction::South
+    })
+}
@@ -0,0 +1,121 @@
+use bevy_spicy_networking::NetworkMessage;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpStream},
+    sync::mpsc::Sender,
+    thread,
+};
+
+use woods_common::{wire, Packet, WireMessage};
+
+// Frames are laid out the way bevy_spicy_networking lays them out: a big-endian u32 length,
+// then a bincode-encoded { kind, data } pair. Bots speak it directly over blocking sockets since
+// NetworkClient only holds one connection per app.
+#[derive(Serialize, Deserialize)]
+struct Frame {
+    kind: String,
+    data: Box<dyn NetworkMessage>,
+}
+
+// Same as NetworkSettings::default
+const MAX_FRAME_LENGTH: usize = 10 * 1024 * 1024;
+
+pub enum Incoming {
+    Packet {
+        bot: usize,
+        packet: Packet,
+        bytes: usize,
+    },
+    Closed {
+        bot: usize,
+        error: Option<io::Error>,
+    },
+}
+
+pub struct Connection {
+    stream: TcpStream,
+    pub bytes_sent: u64,
+}
+
+impl Connection {
+    // Everything the server sends is passed to `incoming`, tagged with `bot`, from a thread of its
+    // own until the connection closes
+    pub fn open(address: SocketAddr, bot: usize, incoming: Sender<Incoming>) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        let mut reader = stream.try_clone()?;
+
+        thread::Builder::new()
+            .name(format!("bot-{}", bot))
+            .spawn(move || loop {
+                match read_frame(&mut reader) {
+                    Ok((packet, bytes)) => {
+                        if incoming
+                            .send(Incoming::Packet { bot, packet, bytes })
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                    Err(err) => {
+                        let error = match err.kind() {
+                            io::ErrorKind::UnexpectedEof => None,
+                            _ => Some(err),
+                        };
+                        let _ = incoming.send(Incoming::Closed { bot, error });
+                        return;
+                    }
+                }
+            })?;
+
+        Ok(Connection {
+            stream,
+            bytes_sent: 0,
+        })
+    }
+
+    pub fn send<T: WireMessage>(&mut self, message: &T) -> io::Result<()> {
+        let frame = Frame {
+            kind: "p".to_string(),
+            data: Box::new(wire::encode(message)),
+        };
+        let body = bincode::serialize(&frame)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        self.stream.write_all(&(body.len() as u32).to_be_bytes())?;
+        self.stream.write_all(&body)?;
+        self.bytes_sent += 4 + body.len() as u64;
+        Ok(())
+    }
+
+    pub fn close(&self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+// Returns the packet and the number of bytes it took on the wire
+fn read_frame(stream: &mut TcpStream) -> io::Result<(Packet, usize)> {
+    let mut length = [0; 4];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_FRAME_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {} bytes is too long", length),
+        ));
+    }
+
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body)?;
+    let frame: Frame = bincode::deserialize(&body)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    match frame.data.downcast::<Packet>() {
+        Ok(packet) => Ok((*packet, 4 + length)),
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected message kind {:?}", frame.kind),
+        )),
+    }
+}
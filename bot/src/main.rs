@@ -0,0 +1,179 @@
+use log::LevelFilter;
+use simple_logger::SimpleLogger;
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use bot::Bot;
+use connection::{Connection, Incoming};
+use options::Options;
+use stats::Stats;
+use woods_common::{
+    wire, Hello, Join, Packet, PlayerLeft, Rejected, Snapshot, Welcome, WireMessage,
+    PROTOCOL_VERSION,
+};
+
+mod bot;
+mod connection;
+mod options;
+mod stats;
+
+// Longest the bots go without checking whether one of them is due to step
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+fn main() {
+    SimpleLogger::new()
+        .with_level(LevelFilter::Off)
+        .with_module_level("woods_bot", LevelFilter::Info)
+        .init()
+        .unwrap();
+
+    let options = match Options::from_args() {
+        Ok(options) => options,
+        Err(err) => {
+            log::error!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    log::info!(
+        "Starting {} bots ({}) against {}",
+        options.credentials.len(),
+        options.behavior,
+        options.server
+    );
+
+    let mut bots: Vec<Bot> = options.credentials.iter().cloned().map(Bot::new).collect();
+    let mut stats = Stats::default();
+    let mut rng = rand::thread_rng();
+    let (incoming_sender, incoming) = mpsc::channel();
+
+    let start = Instant::now();
+    let mut last_report = start;
+    let mut opened = 0;
+
+    loop {
+        let now = Instant::now();
+
+        // Connections are opened gradually so the server sees a ramp rather than a stampede
+        while opened < bots.len() && now >= start + options.ramp * opened as u32 {
+            let bot = &mut bots[opened];
+            match Connection::open(options.server, opened, incoming_sender.clone()) {
+                Ok(connection) => {
+                    bot.connection = Some(connection);
+                    send(
+                        bot,
+                        &mut stats,
+                        &Hello {
+                            version: PROTOCOL_VERSION,
+                        },
+                    );
+                    let join = Join {
+                        session: None,
+                        credentials: bot.credentials.clone(),
+                        name: bot.name().to_string(),
+                    };
+                    send(bot, &mut stats, &join);
+                }
+                Err(err) => {
+                    log::warn!("{} could not connect: {}", bot.name(), err);
+                    stats.disconnected();
+                }
+            }
+            opened += 1;
+        }
+
+        let mut next = incoming.recv_timeout(POLL_INTERVAL);
+        loop {
+            match next {
+                Ok(Incoming::Packet { bot, packet, bytes }) => {
+                    stats.received(bytes);
+                    handle_packet(&mut bots[bot], &mut stats, &packet, Instant::now());
+                }
+                Ok(Incoming::Closed { bot, error }) => {
+                    let bot = &mut bots[bot];
+                    match error {
+                        Some(err) => log::warn!("{} lost its connection: {}", bot.name(), err),
+                        None => log::warn!("{} was disconnected", bot.name()),
+                    }
+                    bot.connection = None;
+                    bot.player = None;
+                    stats.disconnected();
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => unreachable!(),
+            }
+            next = incoming.try_recv().map_err(|_| RecvTimeoutError::Timeout);
+        }
+
+        let now = Instant::now();
+        for bot in bots.iter_mut() {
+            if let Some(move_input) = bot.step(&options.behavior, options.step, now, &mut rng) {
+                send(bot, &mut stats, &move_input);
+            }
+        }
+
+        if now - last_report >= options.report {
+            let connected = bots.iter().filter(|bot| bot.connection.is_some()).count();
+            let joined = bots.iter().filter(|bot| bot.player.is_some()).count();
+            log::info!("{}", stats.report(now - last_report, connected, joined));
+            last_report = now;
+        }
+
+        if matches!(options.duration, Some(duration) if now - start >= duration) {
+            break;
+        }
+    }
+
+    for bot in &bots {
+        if let Some(connection) = &bot.connection {
+            connection.close();
+        }
+    }
+}
+
+fn handle_packet(bot: &mut Bot, stats: &mut Stats, packet: &Packet, now: Instant) {
+    let result = match wire::message_id(packet) {
+        Some(Welcome::ID) => wire::decode::<Welcome>(packet).map(|welcome| {
+            log::debug!("{} joined as {:?}", bot.name(), welcome.player_id);
+            bot.welcome(&welcome, now);
+        }),
+        Some(Snapshot::ID) => wire::decode::<Snapshot>(packet).map(|snapshot| {
+            for latency in bot.apply(&snapshot, now) {
+                stats.latency(latency);
+            }
+        }),
+        Some(PlayerLeft::ID) => wire::decode::<PlayerLeft>(packet).map(|PlayerLeft(player_id)| {
+            bot.forget(player_id);
+        }),
+        Some(Rejected::ID) => wire::decode::<Rejected>(packet).map(|Rejected(reason)| {
+            log::warn!("{} was rejected: {}", bot.name(), reason);
+            stats.rejected();
+        }),
+        // Chat and anything newer than the bot are only counted
+        _ => Ok(()),
+    };
+
+    if let Err(err) = result {
+        log::warn!("{} received a malformed message: {}", bot.name(), err);
+    }
+}
+
+fn send<T: WireMessage>(bot: &mut Bot, stats: &mut Stats, message: &T) {
+    let connection = match &mut bot.connection {
+        Some(connection) => connection,
+        None => return,
+    };
+
+    let before = connection.bytes_sent;
+    match connection.send(message) {
+        Ok(()) => stats.sent(connection.bytes_sent - before),
+        Err(err) => {
+            let name = bot.credentials.username();
+            log::warn!("{} could not send {}: {}", name, T::NAME, err);
+            // The reader thread reports the disconnect
+            connection.close();
+        }
+    }
+}
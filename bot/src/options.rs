@@ -0,0 +1,148 @@
+use std::{
+    env, fmt, fs,
+    net::{SocketAddr, ToSocketAddrs},
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+use woods_common::{validate_name, Credentials, SERVER_PORT};
+
+use crate::bot::Behavior;
+
+const USAGE: &str = "Usage: woods-bot [OPTIONS]
+
+Connects a number of scripted players to a server and reports how it keeps up.
+
+Options:
+    --server <ADDRESS>      Server to connect to [WOODS_SERVER] (default: 127.0.0.1:14192)
+    --count <N>             Number of bots (default: 1, or one per line of --tokens)
+    --behavior <BEHAVIOR>   wander, idle or follow:<NAME> (default: wander)
+    --prefix <NAME>         Bots log in as <NAME>1, <NAME>2, ... with the password in
+                            WOODS_BOT_PASSWORD (default: bot)
+    --tokens <PATH>         Log in with the tokens in this file, one per line, instead
+    --step-ms <MS>          Time between a bot's steps; under 300 runs (default: 300)
+    --ramp-ms <MS>          Time between opening connections (default: 20)
+    --report-secs <SECS>    How often to print statistics (default: 5)
+    --duration-secs <SECS>  Stop after this long (default: run until killed)
+    -h, --help              Print this message
+
+Accounts for --prefix can be created with `woods-server account add`.";
+
+pub struct Options {
+    pub server: SocketAddr,
+    pub behavior: Behavior,
+    // One per bot
+    pub credentials: Vec<Credentials>,
+    pub step: Duration,
+    pub ramp: Duration,
+    pub report: Duration,
+    pub duration: Option<Duration>,
+}
+
+impl Options {
+    pub fn from_args() -> Result<Self, String> {
+        let mut args = env::args().skip(1);
+        let mut server = env::var("WOODS_SERVER").ok();
+        let mut count: Option<usize> = None;
+        let mut behavior = Behavior::Wander;
+        let mut prefix = "bot".to_string();
+        let mut tokens: Option<PathBuf> = None;
+        let mut step_ms = 300;
+        let mut ramp_ms = 20;
+        let mut report_secs = 5;
+        let mut duration_secs: Option<u64> = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("{} requires a value\n\n{}", arg, USAGE))
+            };
+
+            match arg.as_str() {
+                "--server" => server = Some(value()?),
+                "--count" => count = Some(parse(&arg, &value()?)?),
+                "--behavior" => behavior = parse(&arg, &value()?)?,
+                "--prefix" => prefix = value()?,
+                "--tokens" => tokens = Some(value()?.into()),
+                "--step-ms" => step_ms = parse(&arg, &value()?)?,
+                "--ramp-ms" => ramp_ms = parse(&arg, &value()?)?,
+                "--report-secs" => report_secs = parse(&arg, &value()?)?,
+                "--duration-secs" => duration_secs = Some(parse(&arg, &value()?)?),
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                _ => return Err(format!("Unknown argument {:?}\n\n{}", arg, USAGE)),
+            }
+        }
+
+        let credentials = match tokens {
+            Some(path) => {
+                let text = fs::read_to_string(&path)
+                    .map_err(|err| format!("Could not read tokens {:?}: {}", path, err))?;
+                let tokens: Vec<Credentials> = text
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(|token| Credentials::Token(token.to_string()))
+                    .collect();
+                let count = count.unwrap_or_else(|| tokens.len());
+                if count > tokens.len() {
+                    return Err(format!(
+                        "{} bots need {} tokens but {:?} only has {}",
+                        count,
+                        count,
+                        path,
+                        tokens.len()
+                    ));
+                }
+                tokens.into_iter().take(count).collect()
+            }
+            None => {
+                let password = env::var("WOODS_BOT_PASSWORD")
+                    .map_err(|_| "Set WOODS_BOT_PASSWORD or pass --tokens".to_string())?;
+                (1..=count.unwrap_or(1))
+                    .map(|n| {
+                        Ok(Credentials::Password {
+                            username: validate_name(&format!("{}{}", prefix, n))?,
+                            password: password.clone(),
+                        })
+                    })
+                    .collect::<Result<_, String>>()?
+            }
+        };
+
+        Ok(Options {
+            server: match server {
+                Some(address) => resolve(&address)?,
+                None => ([127, 0, 0, 1], SERVER_PORT).into(),
+            },
+            behavior,
+            credentials,
+            step: Duration::from_millis(step_ms),
+            ramp: Duration::from_millis(ramp_ms),
+            report: Duration::from_secs(report_secs),
+            duration: duration_secs.map(Duration::from_secs),
+        })
+    }
+}
+
+// Accepts host:port, or just a host to use the default port
+fn resolve(address: &str) -> Result<SocketAddr, String> {
+    address
+        .to_socket_addrs()
+        .or_else(|_| (address, SERVER_PORT).to_socket_addrs())
+        .ok()
+        .and_then(|mut addresses| addresses.next())
+        .ok_or_else(|| format!("Could not resolve {:?}", address))
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|err| format!("Invalid value {:?} for {}: {}", value, name, err))
+}
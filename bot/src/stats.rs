@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+// Counts since the last report, except for the totals
+#[derive(Default)]
+pub struct Stats {
+    latencies: Vec<Duration>,
+    messages_in: u64,
+    bytes_in: u64,
+    messages_out: u64,
+    bytes_out: u64,
+    disconnects: u32,
+    total_disconnects: u32,
+    total_rejections: u32,
+}
+
+impl Stats {
+    pub fn received(&mut self, bytes: usize) {
+        self.messages_in += 1;
+        self.bytes_in += bytes as u64;
+    }
+
+    pub fn sent(&mut self, bytes: u64) {
+        self.messages_out += 1;
+        self.bytes_out += bytes;
+    }
+
+    pub fn latency(&mut self, latency: Duration) {
+        self.latencies.push(latency);
+    }
+
+    pub fn disconnected(&mut self) {
+        self.disconnects += 1;
+        self.total_disconnects += 1;
+    }
+
+    pub fn rejected(&mut self) {
+        self.total_rejections += 1;
+    }
+
+    // One line covering everything since the last report, which starts a new period
+    pub fn report(&mut self, elapsed: Duration, connected: usize, joined: usize) -> String {
+        let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+        self.latencies.sort_unstable();
+        let percentile = |p: f64| match self.latencies.len() {
+            0 => "-".to_string(),
+            n => format!("{:?}", self.latencies[((n - 1) as f64 * p) as usize]),
+        };
+
+        let line = format!(
+            "{} connected, {} joined | in {:.0} msg/s {:.1} KiB/s | out {:.0} msg/s {:.1} KiB/s | \
+             move latency p50 {} p95 {} max {} ({} samples) | {} disconnects ({} total), {} rejected",
+            connected,
+            joined,
+            self.messages_in as f64 / seconds,
+            self.bytes_in as f64 / 1024.0 / seconds,
+            self.messages_out as f64 / seconds,
+            self.bytes_out as f64 / 1024.0 / seconds,
+            percentile(0.5),
+            percentile(0.95),
+            percentile(1.0),
+            self.latencies.len(),
+            self.disconnects,
+            self.total_disconnects,
+            self.total_rejections,
+        );
+
+        *self = Stats {
+            total_disconnects: self.total_disconnects,
+            total_rejections: self.total_rejections,
+            ..Default::default()
+        };
+        line
+    }
+}
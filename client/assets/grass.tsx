@@ -1,6 +1,6 @@
 <?xml version="1.0" encoding="UTF-8"?>
-<tileset version="1.5" tiledversion="1.7.2" name="grass" tilewidth="20" tileheight="20" tilecount="20" columns="5">
- <image source="grass.png" width="100" height="80"/>
+<tileset version="1.5" tiledversion="1.7.2" name="grass" tilewidth="20" tileheight="20" tilecount="50" columns="10">
+ <image source="grass.png" width="200" height="100"/>
  <tile id="3" probability="0.2"/>
  <tile id="4" probability="0.5"/>
  <tile id="5" probability="2"/>
@@ -0,0 +1,308 @@
+use bevy::{
+    input::{
+        gamepad::{
+            Gamepad, GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType, GamepadEvent,
+            GamepadEventType,
+        },
+        InputSystem,
+    },
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use woods_common::Direction;
+
+use crate::map::assets_dir;
+
+const BINDINGS_FILE: &str = "bindings.toml";
+
+// How often the bindings file is checked for edits
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+// Turns keys, buttons and sticks into Actions, kept in an Input<Action> resource that works like
+// bevy's Input<KeyCode>
+pub struct ActionsPlugin;
+
+impl Plugin for ActionsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let path = assets_dir().join(BINDINGS_FILE);
+        let bindings = Bindings::load(&path).unwrap_or_else(|err| {
+            log::error!("{}; using the default bindings", err);
+            Bindings::default()
+        });
+
+        app.insert_resource(bindings)
+            .insert_resource(BindingsFile {
+                modified: modified(&path),
+                path,
+                timer: Timer::new(RELOAD_INTERVAL, true),
+            })
+            .init_resource::<Input<Action>>()
+            .init_resource::<Gamepads>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                track_gamepads.system().label("track_gamepads"),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                update_actions
+                    .system()
+                    .after(InputSystem)
+                    .after("track_gamepads"),
+            )
+            .add_system(reload_bindings.system());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Move(Direction),
+    Interact,
+    Chat,
+    Run,
+    Overlay,
+}
+
+impl Action {
+    pub const ALL: [Action; 8] = [
+        Action::Move(Direction::North),
+        Action::Move(Direction::South),
+        Action::Move(Direction::East),
+        Action::Move(Direction::West),
+        Action::Interact,
+        Action::Chat,
+        Action::Run,
+        Action::Overlay,
+    ];
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Binding {
+    pub keys: Vec<KeyCode>,
+    pub buttons: Vec<GamepadButtonType>,
+}
+
+impl Binding {
+    fn new(keys: &[KeyCode], buttons: &[GamepadButtonType]) -> Self {
+        Binding {
+            keys: keys.to_vec(),
+            buttons: buttons.to_vec(),
+        }
+    }
+}
+
+// What sets off each action. Read from assets/bindings.toml, which is picked up again whenever it
+// changes, and can be rebound from code with bind_key and bind_button.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bindings {
+    pub move_north: Binding,
+    pub move_south: Binding,
+    pub move_east: Binding,
+    pub move_west: Binding,
+    pub interact: Binding,
+    pub chat: Binding,
+    pub run: Binding,
+    pub overlay: Binding,
+    // How far a stick or analog d-pad has to be pushed to move; the left stick always moves
+    pub stick_threshold: f32,
+    // A move let go sooner than this after turning to face it only turns; held any longer, it
+    // starts walking
+    pub turn_threshold_ms: u64,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use GamepadButtonType::*;
+
+        Bindings {
+            move_north: Binding::new(&[KeyCode::Up, KeyCode::W], &[DPadUp]),
+            move_south: Binding::new(&[KeyCode::Down, KeyCode::S], &[DPadDown]),
+            move_east: Binding::new(&[KeyCode::Right, KeyCode::D], &[DPadRight]),
+            move_west: Binding::new(&[KeyCode::Left, KeyCode::A], &[DPadLeft]),
+            interact: Binding::new(&[KeyCode::Space, KeyCode::E], &[South]),
+            chat: Binding::new(&[KeyCode::Return], &[]),
+            run: Binding::new(&[KeyCode::LShift, KeyCode::RShift], &[West]),
+            overlay: Binding::new(&[KeyCode::F3], &[]),
+            stick_threshold: 0.5,
+            turn_threshold_ms: 120,
+        }
+    }
+}
+
+impl Bindings {
+    // A missing file means the defaults
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                toml::from_str(&text).map_err(|err| format!("Invalid bindings {:?}: {}", path, err))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Bindings::default()),
+            Err(err) => Err(format!("Could not read bindings {:?}: {}", path, err)),
+        }
+    }
+
+    pub fn turn_threshold(&self) -> Duration {
+        Duration::from_millis(self.turn_threshold_ms)
+    }
+
+    pub fn get(&self, action: Action) -> &Binding {
+        match action {
+            Action::Move(Direction::North) => &self.move_north,
+            Action::Move(Direction::South) => &self.move_south,
+            Action::Move(Direction::East) => &self.move_east,
+            Action::Move(Direction::West) => &self.move_west,
+            Action::Interact => &self.interact,
+            Action::Chat => &self.chat,
+            Action::Run => &self.run,
+            Action::Overlay => &self.overlay,
+        }
+    }
+
+    fn get_mut(&mut self, action: Action) -> &mut Binding {
+        match action {
+            Action::Move(Direction::North) => &mut self.move_north,
+            Action::Move(Direction::South) => &mut self.move_south,
+            Action::Move(Direction::East) => &mut self.move_east,
+            Action::Move(Direction::West) => &mut self.move_west,
+            Action::Interact => &mut self.interact,
+            Action::Chat => &mut self.chat,
+            Action::Run => &mut self.run,
+            Action::Overlay => &mut self.overlay,
+        }
+    }
+
+    // Moves a key to `action`, taking it away from whatever it did before
+    pub fn bind_key(&mut self, action: Action, key: KeyCode) {
+        for other in Action::ALL.iter() {
+            self.get_mut(*other).keys.retain(|bound| *bound != key);
+        }
+        self.get_mut(action).keys.push(key);
+    }
+
+    pub fn bind_button(&mut self, action: Action, button: GamepadButtonType) {
+        for other in Action::ALL.iter() {
+            self.get_mut(*other)
+                .buttons
+                .retain(|bound| *bound != button);
+        }
+        self.get_mut(action).buttons.push(button);
+    }
+}
+
+struct BindingsFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    timer: Timer,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// Bevy 0.5 doesn't keep a list of connected gamepads, and their axes can only be read one by one
+#[derive(Default)]
+struct Gamepads(HashSet<Gamepad>);
+
+fn track_gamepads(mut gamepads: ResMut<Gamepads>, mut events: EventReader<GamepadEvent>) {
+    for GamepadEvent(gamepad, event_type) in events.iter() {
+        match event_type {
+            GamepadEventType::Connected => {
+                gamepads.0.insert(*gamepad);
+            }
+            GamepadEventType::Disconnected => {
+                gamepads.0.remove(gamepad);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn update_actions(
+    bindings: Res<Bindings>,
+    gamepads: Res<Gamepads>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut actions: ResMut<Input<Action>>,
+) {
+    actions.clear();
+
+    for action in Action::ALL.iter().copied() {
+        let binding = bindings.get(action);
+        let held = binding.keys.iter().any(|key| keys.pressed(*key))
+            || gamepads.0.iter().any(|gamepad| {
+                binding
+                    .buttons
+                    .iter()
+                    .any(|button| buttons.pressed(GamepadButton(*gamepad, *button)))
+                    || match action {
+                        Action::Move(direction) => {
+                            stick_pushed(&axes, *gamepad, direction, bindings.stick_threshold)
+                        }
+                        _ => false,
+                    }
+            });
+
+        if held {
+            actions.press(action);
+        } else if actions.pressed(action) {
+            actions.release(action);
+        }
+    }
+}
+
+fn stick_pushed(
+    axes: &Axis<GamepadAxis>,
+    gamepad: Gamepad,
+    direction: Direction,
+    threshold: f32,
+) -> bool {
+    let stick = |x, y| {
+        Vec2::new(
+            axes.get(GamepadAxis(gamepad, x)).unwrap_or(0.0),
+            axes.get(GamepadAxis(gamepad, y)).unwrap_or(0.0),
+        )
+    };
+
+    [
+        stick(GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY),
+        stick(GamepadAxisType::DPadX, GamepadAxisType::DPadY),
+    ]
+    .iter()
+    .any(|pushed| pushed.dot(direction.translation()) > threshold)
+}
+
+fn reload_bindings(
+    time: Res<Time>,
+    mut file: ResMut<BindingsFile>,
+    mut bindings: ResMut<Bindings>,
+) {
+    if !file.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let modified = modified(&file.path);
+    if modified == file.modified {
+        return;
+    }
+    file.modified = modified;
+
+    match Bindings::load(&file.path) {
+        Ok(loaded) => {
+            log::info!("Reloaded bindings from {:?}", file.path);
+            *bindings = loaded;
+        }
+        // Keeps the old bindings so a half-saved file doesn't leave the player stuck
+        Err(err) => log::error!("{}", err),
+    }
+}
@@ -0,0 +1,239 @@
+use bevy::prelude::*;
+use bevy_spicy_networking::NetworkClient;
+use std::{collections::VecDeque, time::Duration};
+
+use woods_common::{Chat, Say, MAX_CHAT_LENGTH};
+
+use crate::{
+    actions::Action,
+    net_stats::NetStats,
+    network::{Players, Session},
+    player::DisplayName,
+    ui::UiFont,
+    wire::{self, AppWireMessage, Received},
+    SCREEN_WIDTH,
+};
+
+// Lines kept on screen in the chat log
+const LOG_LINES: usize = 8;
+
+const BUBBLE_DURATION: Duration = Duration::from_secs(5);
+const BUBBLE_LENGTH: usize = 40;
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ChatInput>()
+            .init_resource::<ChatLog>()
+            .add_startup_system(setup_chat.system().after("load_font"))
+            .add_system(chat_input.system().label("chat_input"))
+            .add_system(handle_chat.system())
+            .add_system(update_chat_text.system())
+            .add_system(expire_speech_bubbles.system())
+            .listen_for_wire_message::<Chat>();
+    }
+}
+
+// While active, typed characters go to the chat box instead of moving the player
+#[derive(Default)]
+pub struct ChatInput {
+    pub active: bool,
+    text: String,
+}
+
+#[derive(Default)]
+struct ChatLog(VecDeque<String>);
+
+struct ChatText;
+
+struct SpeechBubble {
+    speaker: Entity,
+    timer: Timer,
+}
+
+fn setup_chat(mut commands: Commands, ui_font: Res<UiFont>) {
+    let style = TextStyle {
+        font: ui_font.0.clone(),
+        font_size: 14.0,
+        color: Color::WHITE,
+    };
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(5.0),
+                    bottom: Val::Px(5.0),
+                    ..Default::default()
+                },
+                max_size: Size::new(Val::Px(SCREEN_WIDTH - 10.0), Val::Undefined),
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: String::new(),
+                        style: style.clone(),
+                    },
+                    TextSection {
+                        value: String::new(),
+                        style: TextStyle {
+                            color: Color::YELLOW,
+                            ..style
+                        },
+                    },
+                ],
+                alignment: Default::default(),
+            },
+            ..Default::default()
+        })
+        .insert(ChatText);
+}
+
+fn chat_input(
+    mut input: ResMut<ChatInput>,
+    mut characters: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<Input<KeyCode>>,
+    actions: Res<Input<Action>>,
+    session: Res<Session>,
+    net: Res<NetworkClient>,
+    mut stats: ResMut<NetStats>,
+) {
+    if !session.joined() {
+        if input.active {
+            *input = ChatInput::default();
+        }
+        return;
+    }
+
+    if !input.active {
+        // Anything typed while the chat box was closed was meant for something else
+        characters.iter().for_each(drop);
+        if actions.just_pressed(Action::Chat) {
+            input.active = true;
+        }
+        return;
+    }
+
+    for event in characters.iter() {
+        if !event.char.is_control() && input.text.chars().count() < MAX_CHAT_LENGTH {
+            input.text.push(event.char);
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        input.text.pop();
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        *input = ChatInput::default();
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        let text = std::mem::take(&mut input.text);
+        input.active = false;
+        if !text.trim().is_empty() {
+            wire::send(&net, &mut stats, &Say(text));
+        }
+    }
+}
+
+fn handle_chat(
+    mut commands: Commands,
+    mut log: ResMut<ChatLog>,
+    mut chats: EventReader<Received<Chat>>,
+    players: Res<Players>,
+    ui_font: Res<UiFont>,
+    name_query: Query<&DisplayName>,
+    bubble_query: Query<(Entity, &SpeechBubble)>,
+) {
+    for chat in chats.iter() {
+        let Chat { player_id, text } = &**chat;
+        let speaker = player_id.and_then(|player_id| players.0.get(&player_id).copied());
+
+        let line = match (player_id, speaker.map(|speaker| name_query.get(speaker))) {
+            (Some(_), Some(Ok(DisplayName(name)))) => format!("{}: {}", name, text),
+            (Some(player_id), _) => format!("Player {}: {}", player_id.0, text),
+            (None, _) => format!("* {}", text),
+        };
+        log.0.push_back(line);
+        while log.0.len() > LOG_LINES {
+            log.0.pop_front();
+        }
+
+        let speaker = match speaker {
+            Some(speaker) => speaker,
+            None => continue,
+        };
+
+        // A new message replaces whatever the player was saying before
+        for (bubble, SpeechBubble { speaker: other, .. }) in bubble_query.iter() {
+            if *other == speaker {
+                commands.entity(bubble).despawn();
+            }
+        }
+
+        let mut bubble_text: String = text.chars().take(BUBBLE_LENGTH).collect();
+        if text.chars().count() > BUBBLE_LENGTH {
+            bubble_text.push('…');
+        }
+
+        let bubble = commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::with_section(
+                    bubble_text,
+                    TextStyle {
+                        font: ui_font.0.clone(),
+                        font_size: 12.0,
+                        color: Color::BLACK,
+                    },
+                    TextAlignment {
+                        vertical: VerticalAlign::Bottom,
+                        horizontal: HorizontalAlign::Center,
+                    },
+                ),
+                // Above the name label; see update_name_labels
+                transform: Transform::from_xyz(0.0, 36.0, 0.5),
+                ..Default::default()
+            })
+            .insert(SpeechBubble {
+                speaker,
+                timer: Timer::new(BUBBLE_DURATION, false),
+            })
+            .id();
+        commands.entity(speaker).push_children(&[bubble]);
+    }
+}
+
+fn expire_speech_bubbles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut SpeechBubble)>,
+) {
+    for (bubble, mut speech_bubble) in query.iter_mut() {
+        if speech_bubble.timer.tick(time.delta()).finished() {
+            commands.entity(bubble).despawn();
+        }
+    }
+}
+
+fn update_chat_text(
+    input: Res<ChatInput>,
+    log: Res<ChatLog>,
+    mut query: Query<&mut Text, With<ChatText>>,
+) {
+    if !input.is_changed() && !log.is_changed() {
+        return;
+    }
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = log.0.iter().map(|line| format!("{}\n", line)).collect();
+        text.sections[1].value = if input.active {
+            format!("> {}_", input.text)
+        } else {
+            String::new()
+        };
+    }
+}
@@ -0,0 +1,387 @@
+use bevy::prelude::*;
+use bevy_spicy_networking::ClientNetworkEvent;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+use woods_common::{validate_name, Credentials, Rejected, Welcome, SERVER_PORT};
+
+use crate::{network::Session, ui::UiFont, wire::Received};
+
+pub struct ConnectPlugin;
+
+impl Plugin for ConnectPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ConnectOptions>()
+            .add_startup_system(setup_connect_screen.system().after("load_font"))
+            .add_system(connect_screen_input.system())
+            .add_system(
+                connect_screen_network_events
+                    .system()
+                    .after("network_events"),
+            )
+            .add_system(update_connect_screen_text.system());
+    }
+}
+
+// Asks the network plugin to connect to a server; the connect screen sends one, as can anything
+// driving the client headless
+pub struct Connect {
+    pub address: SocketAddr,
+    pub credentials: Credentials,
+    // What to be called in the world
+    pub name: String,
+}
+
+// Given on the command line; when a server and credentials are set the client connects without
+// waiting for input. Passwords are only read from the environment so they stay out of `ps`.
+#[derive(Default)]
+pub struct ConnectOptions {
+    pub server: Option<SocketAddr>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub token: Option<String>,
+    pub name: Option<String>,
+}
+
+impl ConnectOptions {
+    pub fn from_args() -> Result<Self, String> {
+        let mut args = std::env::args().skip(1);
+        let mut address = std::env::var("WOODS_SERVER").ok();
+        let mut username = std::env::var("WOODS_USERNAME").ok();
+        let mut token = std::env::var("WOODS_TOKEN").ok();
+        let mut name = std::env::var("WOODS_NAME").ok();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--server" => {
+                    address = Some(args.next().ok_or("--server requires an address")?);
+                }
+                "--username" => {
+                    username = Some(args.next().ok_or("--username requires a name")?);
+                }
+                "--token" => {
+                    token = Some(args.next().ok_or("--token requires a token")?);
+                }
+                "--name" => {
+                    name = Some(args.next().ok_or("--name requires a name")?);
+                }
+                _ => return Err(format!("Unknown argument {:?}", arg)),
+            }
+        }
+
+        Ok(ConnectOptions {
+            server: address
+                .map(|address| parse_server_address(&address))
+                .transpose()?,
+            username: username.map(|name| validate_name(&name)).transpose()?,
+            password: std::env::var("WOODS_PASSWORD").ok(),
+            token,
+            name: name.map(|name| validate_name(&name)).transpose()?,
+        })
+    }
+}
+
+// Accepts host:port, or just a host to use the default port
+pub fn parse_server_address(address: &str) -> Result<SocketAddr, String> {
+    let address = address.trim();
+    address
+        .to_socket_addrs()
+        .or_else(|_| (address, SERVER_PORT).to_socket_addrs())
+        .ok()
+        .and_then(|mut addresses| addresses.next())
+        .ok_or_else(|| format!("Could not resolve {:?}", address))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Address,
+    Username,
+    Password,
+    Name,
+}
+
+impl Field {
+    fn next(self) -> Self {
+        match self {
+            Field::Address => Field::Username,
+            Field::Username => Field::Password,
+            Field::Password => Field::Name,
+            Field::Name => Field::Address,
+        }
+    }
+}
+
+struct ConnectScreen {
+    address: String,
+    username: String,
+    password: String,
+    token: Option<String>,
+    // Left empty to be called after the account
+    name: String,
+    // Which field typing goes to
+    field: Field,
+    status: String,
+    connecting: bool,
+    // Keeps the server's reason on screen when it closes the connection after rejecting us
+    rejected: bool,
+}
+
+impl ConnectScreen {
+    // A typed password wins over a token from the command line
+    fn credentials(&self) -> Result<Credentials, String> {
+        match &self.token {
+            _ if !self.password.is_empty() => Ok(Credentials::Password {
+                username: validate_name(&self.username)?,
+                password: self.password.clone(),
+            }),
+            Some(token) => Ok(Credentials::Token(token.clone())),
+            None => Err("Please enter your password".to_string()),
+        }
+    }
+
+    fn name(&self, credentials: &Credentials) -> Result<String, String> {
+        if self.name.trim().is_empty() {
+            validate_name(credentials.username())
+        } else {
+            validate_name(&self.name)
+        }
+    }
+
+    fn connect(&self) -> Result<Connect, String> {
+        let address = parse_server_address(&self.address)?;
+        let credentials = self.credentials()?;
+        let name = self.name(&credentials)?;
+        Ok(Connect {
+            address,
+            credentials,
+            name,
+        })
+    }
+}
+
+struct ConnectScreenRoot;
+
+struct ConnectScreenText;
+
+fn setup_connect_screen(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut connects: EventWriter<Connect>,
+    options: Res<ConnectOptions>,
+    ui_font: Res<UiFont>,
+) {
+    let token_username = options
+        .token
+        .as_ref()
+        .map(|token| Credentials::Token(token.clone()).username().to_string());
+
+    let mut screen = ConnectScreen {
+        address: format!("127.0.0.1:{}", SERVER_PORT),
+        username: options
+            .username
+            .clone()
+            .or(token_username)
+            .unwrap_or_default(),
+        password: options.password.clone().unwrap_or_default(),
+        token: options.token.clone(),
+        name: options.name.clone().unwrap_or_default(),
+        field: Field::Address,
+        status: "Type a server address and log in; Tab moves between fields".to_string(),
+        connecting: false,
+        rejected: false,
+    };
+
+    if let Some(address) = options.server {
+        screen.address = address.to_string();
+        screen.field = Field::Username;
+        if let Ok(connect) = screen.connect() {
+            connects.send(connect);
+            screen.status = format!("Connecting to {}...", address);
+            screen.connecting = true;
+        }
+    }
+
+    spawn_connect_screen(&mut commands, &mut materials, &ui_font);
+    commands.insert_resource(screen);
+}
+
+fn spawn_connect_screen(
+    commands: &mut Commands,
+    materials: &mut Assets<ColorMaterial>,
+    ui_font: &UiFont,
+) {
+    let style = TextStyle {
+        font: ui_font.0.clone(),
+        font_size: 20.0,
+        color: Color::WHITE,
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.8).into()),
+            ..Default::default()
+        })
+        .insert(ConnectScreenRoot)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text {
+                        sections: vec![
+                            TextSection {
+                                value: String::new(),
+                                style: style.clone(),
+                            },
+                            TextSection {
+                                value: String::new(),
+                                style: TextStyle {
+                                    color: Color::GRAY,
+                                    ..style
+                                },
+                            },
+                        ],
+                        alignment: Default::default(),
+                    },
+                    ..Default::default()
+                })
+                .insert(ConnectScreenText);
+        });
+}
+
+fn connect_screen_input(
+    mut screen: ResMut<ConnectScreen>,
+    mut characters: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut connects: EventWriter<Connect>,
+    root_query: Query<Entity, With<ConnectScreenRoot>>,
+) {
+    if root_query.iter().next().is_none() || screen.connecting {
+        return;
+    }
+
+    let screen = &mut *screen;
+    let field = match screen.field {
+        Field::Address => &mut screen.address,
+        Field::Username => &mut screen.username,
+        Field::Password => &mut screen.password,
+        Field::Name => &mut screen.name,
+    };
+
+    for event in characters.iter() {
+        if !event.char.is_control() {
+            field.push(event.char);
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        field.pop();
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        screen.field = screen.field.next();
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        match screen.connect() {
+            Ok(connect) => {
+                screen.status = format!("Connecting to {}...", connect.address);
+                connects.send(connect);
+                screen.connecting = true;
+                screen.rejected = false;
+            }
+            Err(err) => screen.status = err,
+        }
+    }
+}
+
+fn connect_screen_network_events(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut screen: ResMut<ConnectScreen>,
+    session: Res<Session>,
+    mut network_events: EventReader<ClientNetworkEvent>,
+    mut welcomes: EventReader<Received<Welcome>>,
+    mut rejections: EventReader<Received<Rejected>>,
+    ui_font: Res<UiFont>,
+    root_query: Query<Entity, With<ConnectScreenRoot>>,
+) {
+    let mut disconnected = false;
+
+    for event in network_events.iter() {
+        let status = match (event, session.reconnect_attempt()) {
+            (ClientNetworkEvent::Connected, _) => {
+                screen.status = "Connected; waiting for the server...".to_string();
+                continue;
+            }
+            (_, Some(attempt)) => Some(format!(
+                "Connection lost; reconnecting (attempt {})...",
+                attempt
+            )),
+            (ClientNetworkEvent::Disconnected, None) if screen.rejected => None,
+            (ClientNetworkEvent::Disconnected, None) => {
+                Some("Disconnected from server".to_string())
+            }
+            (ClientNetworkEvent::Error(err), None) => Some(format!("Could not connect: {:?}", err)),
+        };
+
+        if let Some(status) = status {
+            screen.status = status;
+        }
+        // Input stays locked while the client retries on its own
+        screen.connecting = session.reconnect_attempt().is_some();
+        disconnected = true;
+    }
+
+    if disconnected && root_query.iter().next().is_none() {
+        spawn_connect_screen(&mut commands, &mut materials, &ui_font);
+    }
+
+    for rejection in rejections.iter() {
+        let Rejected(reason) = &**rejection;
+        screen.status = format!("Rejected by server: {}", reason);
+        screen.rejected = true;
+    }
+
+    if welcomes.iter().next().is_some() {
+        for root in root_query.iter() {
+            commands.entity(root).despawn_recursive();
+        }
+        screen.connecting = false;
+    }
+}
+
+fn update_connect_screen_text(
+    screen: Res<ConnectScreen>,
+    mut text_query: Query<&mut Text, With<ConnectScreenText>>,
+    added_query: Query<Entity, Added<ConnectScreenText>>,
+) {
+    if !screen.is_changed() && added_query.iter().next().is_none() {
+        return;
+    }
+
+    for mut text in text_query.iter_mut() {
+        let cursor = |field| {
+            if screen.field == field && !screen.connecting {
+                "_"
+            } else {
+                ""
+            }
+        };
+        text.sections[0].value = format!(
+            "Server:   {}{}\nUsername: {}{}\nPassword: {}{}\nName:     {}{}\n",
+            screen.address,
+            cursor(Field::Address),
+            screen.username,
+            cursor(Field::Username),
+            "*".repeat(screen.password.chars().count()),
+            cursor(Field::Password),
+            screen.name,
+            cursor(Field::Name)
+        );
+        text.sections[1].value = screen.status.clone();
+    }
+}
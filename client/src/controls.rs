@@ -0,0 +1,93 @@
+use bevy::prelude::*;
+
+use woods_common::{Direction, Map, Position, Speed};
+
+use crate::{
+    actions::{Action, Bindings},
+    chat::ChatInput,
+    player::Me,
+    simulation::WalkEvent,
+    walk_animation::WalkAnimation,
+};
+
+// Turns Move actions into WalkEvents for Me. Needs the ActionsPlugin.
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<HeldMove>()
+            .add_system(movement.system().after("chat_input").before("walk"));
+    }
+}
+
+#[derive(Default)]
+struct HeldMove {
+    // The direction pressed most recently, timing how long it has been held
+    held: Option<(Direction, Timer)>,
+    // The last direction pressed and not yet acted on, so presses during a step aren't lost
+    buffered: Option<Direction>,
+}
+
+fn movement(
+    time: Res<Time>,
+    map: Res<Map>,
+    bindings: Res<Bindings>,
+    chat_input: Res<ChatInput>,
+    actions: Res<Input<Action>>,
+    mut held_move: ResMut<HeldMove>,
+    mut query: Query<(Entity, &WalkAnimation, &Position, &Direction), With<Me>>,
+    mut walk_events: EventWriter<WalkEvent>,
+) {
+    // Keys belong to the chat box while it is open
+    if chat_input.active {
+        *held_move = HeldMove::default();
+        return;
+    }
+
+    for action in actions.get_just_pressed() {
+        if let Action::Move(direction) = *action {
+            held_move.held = Some((direction, Timer::new(bindings.turn_threshold(), false)));
+            held_move.buffered = Some(direction);
+        }
+    }
+
+    if matches!(held_move.held, Some((direction, _)) if !actions.pressed(Action::Move(direction))) {
+        held_move.held = None;
+    }
+
+    if let Some((_, timer)) = &mut held_move.held {
+        timer.tick(time.delta());
+    }
+
+    for (entity, walk_animation, position, facing) in query.iter_mut() {
+        // Players can't take another step until the last one is done
+        if walk_animation.running() {
+            continue;
+        }
+
+        // A fresh press turns or steps right away; a held key keeps walking once it has been
+        // held past the threshold
+        let direction = match held_move.buffered.take() {
+            Some(direction) => direction,
+            None => match held_move.held {
+                Some((direction, ref timer)) if timer.finished() => direction,
+                _ => continue,
+            },
+        };
+
+        let speed = if actions.pressed(Action::Run) {
+            Speed::Run
+        } else {
+            Speed::Walk
+        };
+
+        // Already facing a wall or the map edge, holding on would only repeat a move that does
+        // nothing
+        let walk_event = WalkEvent::from(&map, entity, true, *facing, direction, *position, speed);
+        if walk_event.distance == 0 && *facing == direction {
+            continue;
+        }
+
+        walk_events.send(walk_event);
+    }
+}
@@ -0,0 +1,95 @@
+use bevy::{
+    prelude::*,
+    render::camera::{Camera, WindowOrigin},
+};
+
+use woods_common::{Map, Position};
+
+use crate::{
+    map::MapPlugin,
+    overlay::OverlayPlugin,
+    player::{Me, PlayerPlugin},
+    ui::UiPlugin,
+    walk_animation::walk_animation,
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+// Draws what the SimulationPlugin keeps track of. Needs DefaultPlugins and the ActionsPlugin.
+pub struct GraphicsPlugin;
+
+impl Plugin for GraphicsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_plugin(UiPlugin)
+            .add_plugin(MapPlugin)
+            .add_plugin(PlayerPlugin)
+            .add_plugin(OverlayPlugin)
+            .add_startup_system(setup_camera.system())
+            .add_system(create_offset_parent.system())
+            .add_system(
+                walk_animation
+                    .system()
+                    .label("walk_animation")
+                    .after("advance_walks"),
+            )
+            .add_system(camera_movement.system().after("walk_animation"))
+            .add_system_to_stage(CoreStage::PostUpdate, perspective.system());
+    }
+}
+
+fn setup_camera(mut commands: Commands) {
+    let mut camera = OrthographicCameraBundle::new_2d();
+    camera.orthographic_projection.window_origin = WindowOrigin::BottomLeft;
+    commands.spawn_bundle(camera);
+}
+
+pub struct TransformOffset(pub Transform);
+
+fn create_offset_parent(
+    mut commands: Commands,
+    mut query: Query<(Entity, &TransformOffset), Without<Parent>>,
+) {
+    for (entity, transform_offset) in query.iter_mut() {
+        commands
+            .spawn()
+            .insert(transform_offset.0)
+            .insert(GlobalTransform::default())
+            .push_children(&[entity])
+            .id();
+    }
+}
+
+fn camera_movement(
+    mut commands: Commands,
+    map: Res<Map>,
+    me_query: Query<&Transform, (With<Me>, Changed<Transform>)>,
+    camera_query: Query<Entity, With<Camera>>,
+) {
+    if let Ok(transform) = me_query.single() {
+        let camera = camera_query.single().unwrap();
+
+        let mut camera_transform = Transform::from_translation(
+            transform.translation - Vec3::new(SCREEN_WIDTH / 2.0, SCREEN_HEIGHT / 2.0, 0.0),
+        );
+
+        camera_transform.translation.x = camera_transform
+            .translation
+            .x
+            .clamp(0.0, (map.pixel_width() - SCREEN_WIDTH).max(0.0));
+        camera_transform.translation.y = camera_transform
+            .translation
+            .y
+            .clamp(0.0, (map.pixel_height() - SCREEN_HEIGHT).max(0.0));
+
+        camera_transform.translation.z = 999.0;
+
+        commands.entity(camera).insert(camera_transform);
+    }
+}
+
+fn perspective(mut query: Query<(&mut Transform, &Position)>) {
+    // Sprites should render top-to-bottom so things lower down overlap things higher up
+    for (mut transform, position) in query.iter_mut() {
+        let far = 999; // camera is at 1000; see OrthographicCameraBundle
+        transform.translation.z = (far - position.y).into();
+    }
+}
@@ -0,0 +1,128 @@
+use bevy::prelude::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use woods_common::TICK_RATE;
+
+use crate::{network::Players, simulation::WalkEvent, walk_animation::WalkAnimation};
+
+// Longest a step is held back to smooth out uneven arrival, in seconds
+const MAX_DELAY: f64 = 0.2;
+
+// How quickly the delay follows changes in jitter
+const DELAY_SMOOTHING: f64 = 0.1;
+
+// How quickly the baseline drifts back up after an unusually fast snapshot
+const BASELINE_CREEP: f64 = 0.01;
+
+// Each step queued behind the one playing beyond this many speeds the animation up
+const CATCH_UP_AFTER: usize = 1;
+const CATCH_UP_RATE: f32 = 0.5;
+const MAX_CATCH_UP_RATE: f32 = 3.0;
+
+// A player this many steps behind jumps straight to where it ended up
+const SNAP_AFTER: usize = 6;
+
+// Plays other players' steps back in order, at an even pace, however unevenly they arrive
+pub struct InterpolationPlugin;
+
+impl Plugin for InterpolationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<RemoteSteps>()
+            .add_system(play_remote_steps.system().before("walk"));
+    }
+}
+
+struct PendingStep {
+    step: WalkEvent,
+    due: f64,
+}
+
+// Steps other players took, waiting for their turn. Snapshots leave the server evenly but don't
+// arrive that way, so every step is held back by a delay that follows how uneven they have been.
+#[derive(Default)]
+pub struct RemoteSteps {
+    queues: HashMap<Entity, VecDeque<PendingStep>>,
+    // Shortest recent time between the server simulating a tick and its snapshot arriving
+    baseline: Option<f64>,
+    delay: f64,
+}
+
+impl RemoteSteps {
+    // Called as each snapshot arrives; returns when the steps in it should start playing
+    pub fn arrived(&mut self, tick: u32, now: f64) -> f64 {
+        let sent = f64::from(tick) / TICK_RATE;
+        let offset = now - sent;
+        let baseline = match self.baseline {
+            Some(baseline) if baseline < offset => baseline + (offset - baseline) * BASELINE_CREEP,
+            _ => offset,
+        };
+        self.baseline = Some(baseline);
+
+        // Late snapshots eat into the delay, so it has to cover more than the average lateness
+        let jitter = offset - baseline;
+        self.delay += (2.0 * jitter - self.delay) * DELAY_SMOOTHING;
+        self.delay = self.delay.min(MAX_DELAY);
+
+        sent + baseline + self.delay
+    }
+
+    pub fn push(&mut self, player: Entity, step: WalkEvent, due: f64) {
+        self.queues
+            .entry(player)
+            .or_default()
+            .push_back(PendingStep { step, due });
+    }
+
+    // Ticks start over with a new server, so nothing measured so far applies
+    pub fn clear(&mut self) {
+        *self = RemoteSteps::default();
+    }
+}
+
+fn play_remote_steps(
+    time: Res<Time>,
+    players: Res<Players>,
+    mut remote_steps: ResMut<RemoteSteps>,
+    mut commands: Commands,
+    mut query: Query<&mut WalkAnimation>,
+    mut walk_events: EventWriter<WalkEvent>,
+) {
+    let now = time.seconds_since_startup();
+    let in_view: HashSet<Entity> = players.0.values().copied().collect();
+    remote_steps
+        .queues
+        .retain(|player, _| in_view.contains(player));
+
+    for (player, queue) in remote_steps.queues.iter_mut() {
+        let mut walk_animation = match query.get_mut(*player) {
+            Ok(walk_animation) => walk_animation,
+            // Spawned this frame; the commands haven't been applied yet
+            Err(_) => continue,
+        };
+
+        if queue.len() > SNAP_AFTER {
+            let skipped = queue.len();
+            if let Some(last) = queue.drain(..).last() {
+                log::debug!("{:?} fell {} steps behind; snapping", player, skipped);
+                commands.entity(*player).insert(WalkAnimation::default());
+                walk_events.send(WalkEvent {
+                    distance: 0,
+                    ..last.step
+                });
+            }
+            continue;
+        }
+
+        if walk_animation.running() {
+            let behind = queue.len().saturating_sub(CATCH_UP_AFTER) as f32;
+            walk_animation.hurry((1.0 + behind * CATCH_UP_RATE).min(MAX_CATCH_UP_RATE));
+            continue;
+        }
+
+        if matches!(queue.front(), Some(pending) if pending.due <= now) {
+            if let Some(pending) = queue.pop_front() {
+                walk_events.send(pending.step);
+            }
+        }
+    }
+}
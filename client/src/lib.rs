@@ -0,0 +1,19 @@
+pub mod actions;
+pub mod chat;
+pub mod connect;
+pub mod controls;
+pub mod graphics;
+pub mod interpolation;
+pub mod map;
+pub mod net_stats;
+pub mod network;
+pub mod overlay;
+pub mod player;
+pub mod prediction;
+pub mod simulation;
+pub mod ui;
+pub mod walk_animation;
+pub mod wire;
+
+pub const SCREEN_WIDTH: f32 = 600.0;
+pub const SCREEN_HEIGHT: f32 = 400.0;
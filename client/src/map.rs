@@ -0,0 +1,94 @@
+use bevy::prelude::*;
+use std::path::{Path, PathBuf};
+
+use woods_common::Map;
+
+const MAP_FILE: &str = "field.tmx";
+
+// Draws the Map resource, which the SimulationPlugin provides
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_startup_system(setup_map.system());
+    }
+}
+
+pub fn load_map() -> Map {
+    match Map::load(&assets_dir().join(MAP_FILE)) {
+        Ok(map) => map,
+        Err(err) => {
+            log::error!("Could not load map {}: {}", MAP_FILE, err);
+            panic!();
+        }
+    }
+}
+
+// Mirrors how bevy's AssetServer locates its root so both read the same files
+pub fn assets_dir() -> PathBuf {
+    std::env::var("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(Path::to_path_buf))
+                .unwrap_or_default()
+        })
+        .join("assets")
+}
+
+fn setup_map(
+    mut commands: Commands,
+    map: Res<Map>,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    let atlases: Vec<Handle<TextureAtlas>> = map
+        .tilesets
+        .iter()
+        .map(|tileset| {
+            // Tileset images are relative to the map, which lives at the root of the assets
+            let texture_handle = asset_server.load(tileset.image.as_path());
+            let rows = (tileset.tile_count + tileset.columns - 1) / tileset.columns;
+            texture_atlases.add(TextureAtlas::from_grid(
+                texture_handle,
+                Vec2::new(tileset.tile_width as f32, tileset.tile_height as f32),
+                tileset.columns as usize,
+                rows as usize,
+            ))
+        })
+        .collect();
+
+    for (layer_index, layer) in map.layers.iter().enumerate() {
+        log::debug!("Spawning map layer {:?}", layer.name);
+        for (tile_index, gid) in layer.tiles.iter().enumerate() {
+            if *gid == 0 {
+                // Empty cell
+                continue;
+            }
+
+            let tileset_index = match map.tileset_index(*gid) {
+                Some(tileset_index) => tileset_index,
+                None => {
+                    log::warn!("No tileset for gid {} in layer {:?}", gid, layer.name);
+                    continue;
+                }
+            };
+            let tileset = &map.tilesets[tileset_index];
+
+            let position = map.position(tile_index);
+            let translation = Vec3::new(
+                (position.x as u32 * map.tile_width) as f32 + tileset.tile_width as f32 / 2.0,
+                (position.y as u32 * map.tile_height) as f32 + tileset.tile_height as f32 / 2.0,
+                layer_index as f32,
+            );
+
+            commands.spawn_bundle(SpriteSheetBundle {
+                texture_atlas: atlases[tileset_index].clone(),
+                sprite: TextureAtlasSprite::new(gid - tileset.first_gid),
+                transform: Transform::from_translation(translation),
+                ..Default::default()
+            });
+        }
+    }
+}
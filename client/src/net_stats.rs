@@ -0,0 +1,152 @@
+use bevy::prelude::*;
+use bevy_spicy_networking::{NetworkClient, NetworkData};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use woods_common::{wire::DECODE_STAGE, Packet, Ping, Pong, Snapshot};
+
+use crate::{
+    network::Session,
+    wire::{self, AppWireMessage, Received},
+};
+
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+// Pings that haven't been answered in this long are given up on
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How quickly the jitter estimate follows new round trips, as in RFC 3550
+const JITTER_SMOOTHING: f64 = 1.0 / 16.0;
+
+// Measures the connection: round trips, traffic and how far behind the server we are
+pub struct NetStatsPlugin;
+
+impl Plugin for NetStatsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<NetStats>()
+            .insert_resource(PingTimer(Timer::new(PING_INTERVAL, true)))
+            .add_system_to_stage(DECODE_STAGE, count_received.system())
+            .add_system(send_pings.system())
+            .add_system(handle_pongs.system())
+            .add_system(track_server_tick.system())
+            .listen_for_wire_message::<Pong>();
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct Traffic {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+#[derive(Default)]
+pub struct NetStats {
+    pub rtt: Option<Duration>,
+    // Average difference between consecutive round trips
+    pub jitter: Duration,
+    // Per second, over the last full second
+    pub sent: Traffic,
+    pub received: Traffic,
+    pub server_tick: Option<u32>,
+    // Counted since the last full second
+    sending: Traffic,
+    receiving: Traffic,
+    next_sequence: u32,
+    pings: VecDeque<(u32, Instant)>,
+}
+
+impl NetStats {
+    // Bytes are the encoded message, without spicy's framing
+    pub fn count_sent(&mut self, bytes: usize) {
+        self.sending.messages += 1;
+        self.sending.bytes += bytes as u64;
+    }
+}
+
+struct PingTimer(Timer);
+
+fn count_received(mut stats: ResMut<NetStats>, mut packets: EventReader<NetworkData<Packet>>) {
+    for packet in packets.iter() {
+        let Packet(bytes) = &**packet;
+        stats.receiving.messages += 1;
+        stats.receiving.bytes += bytes.len() as u64;
+    }
+}
+
+// Traffic is totalled on the same beat as the pings
+fn send_pings(
+    time: Res<Time>,
+    net: Res<NetworkClient>,
+    session: Res<Session>,
+    mut timer: ResMut<PingTimer>,
+    mut stats: ResMut<NetStats>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    stats.sent = std::mem::take(&mut stats.sending);
+    stats.received = std::mem::take(&mut stats.receiving);
+
+    let now = Instant::now();
+    while matches!(stats.pings.front(), Some((_, sent)) if now - *sent > PING_TIMEOUT) {
+        stats.pings.pop_front();
+    }
+
+    // Round trips from an old connection don't say anything about the next one
+    if !session.joined() {
+        stats.rtt = None;
+        stats.jitter = Duration::default();
+        stats.server_tick = None;
+        stats.pings.clear();
+        return;
+    }
+
+    stats.next_sequence = stats.next_sequence.wrapping_add(1);
+    let sequence = stats.next_sequence;
+    stats.pings.push_back((sequence, now));
+    let ping = Ping {
+        sequence,
+        rtt: stats.rtt.map(|rtt| rtt.as_millis() as u32),
+    };
+    wire::send(&net, &mut stats, &ping);
+}
+
+fn handle_pongs(mut stats: ResMut<NetStats>, mut pongs: EventReader<Received<Pong>>) {
+    for pong in pongs.iter() {
+        let Pong(sequence) = **pong;
+        let sent = match stats
+            .pings
+            .iter()
+            .position(|(pinged, _)| *pinged == sequence)
+        {
+            // Anything sent before it was lost or is answered out of order, so it is dropped too
+            Some(index) => stats.pings.drain(..=index).last().map(|(_, sent)| sent),
+            None => continue,
+        };
+
+        if let Some(sent) = sent {
+            let rtt = sent.elapsed();
+            if let Some(previous) = stats.rtt {
+                let difference = if rtt > previous {
+                    rtt - previous
+                } else {
+                    previous - rtt
+                };
+                let jitter = stats.jitter.as_secs_f64();
+                stats.jitter = Duration::from_secs_f64(
+                    jitter + (difference.as_secs_f64() - jitter) * JITTER_SMOOTHING,
+                );
+            }
+            stats.rtt = Some(rtt);
+        }
+    }
+}
+
+fn track_server_tick(mut stats: ResMut<NetStats>, mut snapshots: EventReader<Received<Snapshot>>) {
+    for snapshot in snapshots.iter() {
+        stats.server_tick = Some(snapshot.tick);
+    }
+}
@@ -1,146 +1,398 @@
-use std::{collections::HashMap, net::SocketAddr};
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
 
 use bevy::prelude::*;
 
-use bevy_spicy_networking::{
-    AppNetworkClientMessage, ClientNetworkEvent, NetworkClient, NetworkData, NetworkSettings,
+use bevy_spicy_networking::{ClientNetworkEvent, NetworkClient, NetworkSettings};
+use woods_common::{
+    Credentials, Direction, EnteredView, Hello, Join, Map, MoveUpdate, PlayerId, PlayerLeft,
+    Position, Rejected, SessionToken, Snapshot, Speed, Welcome, PROTOCOL_VERSION,
 };
-use woods_common::{MoveUpdate, PlayerId, PlayerLeft, Welcome, SERVER_PORT};
 
 use crate::{
-    player::{insert_player, PlayerTextureAtlasHandle},
-    Me, WalkEvent,
+    connect::Connect,
+    interpolation::RemoteSteps,
+    net_stats::NetStats,
+    player::{insert_player, DisplayName, Me},
+    prediction::Prediction,
+    simulation::WalkEvent,
+    walk_animation::WalkAnimation,
+    wire::{self, AppWireMessage, Received, WirePlugin},
 };
 
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+const MAX_RECONNECT_ATTEMPTS: u32 = 10;
+
+#[derive(Default)]
+pub struct Players(pub HashMap<PlayerId, Entity>);
+
 #[derive(Default)]
-struct Players(pub HashMap<PlayerId, Entity>);
+pub struct Session {
+    address: Option<SocketAddr>,
+    // Kept so the client can log in again by itself after a dropped connection
+    credentials: Option<Credentials>,
+    name: String,
+    // Issued by the server in Welcome and presented again when reconnecting
+    token: Option<SessionToken>,
+    joined: bool,
+    rejected: bool,
+    reconnect: Option<Reconnect>,
+}
+
+struct Reconnect {
+    attempt: u32,
+    timer: Timer,
+}
+
+impl Session {
+    pub fn joined(&self) -> bool {
+        self.joined
+    }
+
+    // The attempt the client is waiting to make, if it is trying to get back into the game
+    pub fn reconnect_attempt(&self) -> Option<u32> {
+        self.reconnect.as_ref().map(|reconnect| reconnect.attempt)
+    }
+
+    fn schedule_reconnect(&mut self) {
+        // A failed connection can report both an error and a disconnect
+        if let Some(reconnect) = &self.reconnect {
+            if !reconnect.timer.finished() {
+                return;
+            }
+        }
+
+        let attempt = self.reconnect_attempt().unwrap_or(0) + 1;
+        if attempt > MAX_RECONNECT_ATTEMPTS {
+            log::warn!("Giving up on reconnecting after {} attempts", attempt - 1);
+            self.reconnect = None;
+            return;
+        }
+
+        // Doubles with each attempt up to a cap
+        let delay = (RECONNECT_DELAY * 2u32.pow(attempt - 1)).min(MAX_RECONNECT_DELAY);
+        log::info!("Reconnecting in {:?} (attempt {})", delay, attempt);
+        self.reconnect = Some(Reconnect {
+            attempt,
+            timer: Timer::new(delay, false),
+        });
+    }
+}
 
 pub struct NetworkPlugin;
 
 impl Plugin for NetworkPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_plugin(bevy_spicy_networking::ClientPlugin)
+            .add_plugin(WirePlugin)
+            .add_event::<Connect>()
             .insert_resource(Players::default())
-            .add_startup_system(setup_networking.system())
-            .add_system(handle_network_events.system())
+            .init_resource::<Prediction>()
+            .init_resource::<Session>()
+            .add_system(handle_connect_requests.system())
+            .add_system(handle_network_events.system().label("network_events"))
+            .add_system(reconnect.system())
             .add_system(handle_welcome.system())
-            .add_system(handle_move_updates.system())
+            .add_system(handle_snapshots.system())
             .add_system(handle_player_left.system());
 
-        app.listen_for_client_message::<Welcome>();
-        app.listen_for_client_message::<MoveUpdate>();
-        app.listen_for_client_message::<PlayerLeft>();
+        app.listen_for_wire_message::<Rejected>();
+        app.listen_for_wire_message::<Welcome>();
+        app.listen_for_wire_message::<Snapshot>();
+        app.listen_for_wire_message::<PlayerLeft>();
     }
 }
 
-fn setup_networking(mut net: ResMut<NetworkClient>) {
-    let ip_address = "127.0.0.1".parse().unwrap();
-    let socket_address = SocketAddr::new(ip_address, SERVER_PORT);
-    log::info!("Connecting to server at {:?}", socket_address);
-    net.connect(socket_address, NetworkSettings::default());
+fn handle_connect_requests(
+    mut net: ResMut<NetworkClient>,
+    mut session: ResMut<Session>,
+    mut connects: EventReader<Connect>,
+) {
+    for Connect {
+        address: socket_address,
+        credentials,
+        name,
+    } in connects.iter()
+    {
+        log::info!(
+            "Connecting to server at {:?} as {}",
+            socket_address,
+            credentials.username()
+        );
+        // Try to pick up where we left off when going back to the same server
+        let token = session
+            .token
+            .filter(|_| session.address == Some(*socket_address));
+        *session = Session {
+            address: Some(*socket_address),
+            credentials: Some(credentials.clone()),
+            name: name.clone(),
+            token,
+            ..Default::default()
+        };
+        net.connect(*socket_address, NetworkSettings::default());
+    }
+}
+
+fn reconnect(time: Res<Time>, mut net: ResMut<NetworkClient>, mut session: ResMut<Session>) {
+    let address = match session.address {
+        Some(address) => address,
+        None => return,
+    };
+
+    if let Some(reconnect) = &mut session.reconnect {
+        if reconnect.timer.tick(time.delta()).just_finished() {
+            log::info!(
+                "Reconnecting to server at {:?} (attempt {})",
+                address,
+                reconnect.attempt
+            );
+            net.connect(address, NetworkSettings::default());
+        }
+    }
 }
 
 fn handle_welcome(
     mut commands: Commands,
     mut players: ResMut<Players>,
-    mut welcomes: EventReader<NetworkData<Welcome>>,
+    mut prediction: ResMut<Prediction>,
+    mut session: ResMut<Session>,
+    mut welcomes: EventReader<Received<Welcome>>,
     me_query: Query<Entity, With<Me>>,
 ) {
     let me = me_query.single().unwrap();
-    for network_data in welcomes.iter() {
-        let Welcome(player_id, position) = **network_data;
-        log::info!("[ME] {:?} @ {:?}", player_id, position);
-        commands.entity(me).insert(player_id).insert(position);
+    for welcome in welcomes.iter() {
+        let Welcome {
+            player_id,
+            position,
+            direction,
+            session: token,
+            ref name,
+        } = **welcome;
+        if session.token == Some(token) {
+            log::info!("[ME] resumed {:?} ({}) @ {:?}", player_id, name, position);
+        } else {
+            log::info!("[ME] {:?} ({}) @ {:?}", player_id, name, position);
+        }
+        commands
+            .entity(me)
+            .insert(player_id)
+            .insert(DisplayName(name.clone()))
+            .insert(position)
+            .insert(direction)
+            .insert(WalkAnimation::default());
         players.0.insert(player_id, me);
+        prediction.reset(position, direction);
+
+        session.token = Some(token);
+        session.joined = true;
+        session.reconnect = None;
     }
 }
 
-fn handle_move_updates(
+// Each snapshot is applied in full, in order, so players can come and go within one frame
+fn handle_snapshots(
     mut commands: Commands,
     mut players: ResMut<Players>,
-    mut moves: EventReader<NetworkData<MoveUpdate>>,
-    me_query: Query<Entity, With<Me>>,
-    mut walk_events: EventWriter<WalkEvent>,
-    player_texture_atlas_handle: Res<PlayerTextureAtlasHandle>,
+    mut prediction: ResMut<Prediction>,
+    mut snapshots: EventReader<Received<Snapshot>>,
+    mut remote_steps: ResMut<RemoteSteps>,
+    me_query: Query<(Entity, Option<&Position>), With<Me>>,
+    map: Res<Map>,
+    time: Res<Time>,
 ) {
-    let me = me_query.single().unwrap();
-    for network_data in moves.iter() {
-        let MoveUpdate {
+    let (me, me_position) = me_query.single().unwrap();
+    for snapshot in snapshots.iter() {
+        let Snapshot {
+            tick,
+            entered,
+            moves,
+            left,
+        } = &**snapshot;
+        log::trace!("Tick {}", tick);
+        let due = remote_steps.arrived(*tick, time.seconds_since_startup());
+
+        for entered_view in entered {
+            enter_view(&mut commands, &mut players, entered_view);
+        }
+
+        for &MoveUpdate {
             player_id,
             direction,
             position,
             distance,
-        } = **network_data;
-        log::trace!(
-            "{:?} @ {:?}, {:?} {:?}",
-            player_id,
-            position,
-            direction,
-            distance
-        );
+            speed,
+            sequence,
+        } in moves
+        {
+            log::trace!(
+                "{:?} @ {:?}, {:?} {:?}",
+                player_id,
+                position,
+                direction,
+                distance
+            );
 
-        match players.0.get(&player_id) {
-            Some(player) => {
-                if player.id() == me.id() {
-                    log::trace!("Skipping move for self");
-                    // TODO: if the position from the server doesn't match what we have, correct it
-                    continue;
-                }
+            match players.0.get(&player_id) {
+                Some(player) => {
+                    if player.id() == me.id() {
+                        if let Some((position, direction)) =
+                            prediction.reconcile(&map, sequence, position, direction)
+                        {
+                            log::debug!("[ME] reconciled to {:?} facing {:?}", position, direction);
+                            correct_me(&mut commands, me, me_position, position, direction, speed);
+                        }
+                        continue;
+                    }
 
-                walk_events.send(WalkEvent {
-                    player: *player,
-                    me: false,
-                    direction,
-                    to: position,
-                    distance,
-                });
+                    remote_steps.push(
+                        *player,
+                        WalkEvent {
+                            player: *player,
+                            me: false,
+                            direction,
+                            to: position,
+                            distance,
+                            speed,
+                        },
+                        due,
+                    );
+                }
+                None => {
+                    log::warn!("MoveUpdate for {:?}, which is not in view", player_id);
+                }
             }
-            None => {
-                log::debug!(
-                    "New player seen {:?} at {:?} facing {:?}",
-                    player_id,
-                    position,
-                    direction
-                );
-                let player = insert_player(
-                    &mut commands,
-                    player_texture_atlas_handle.clone(),
-                    direction,
-                    position,
-                );
-                players.0.insert(player_id, player);
-                walk_events.send(WalkEvent {
-                    player,
-                    me: false,
-                    direction,
-                    to: position,
-                    distance,
-                });
+        }
+
+        for player_id in left {
+            if let Some(player) = players.0.remove(player_id) {
+                commands.entity(player).despawn_recursive();
+                log::trace!("{:?} went out of view.", player_id);
             }
         }
     }
 }
 
-fn handle_network_events(mut network_events: EventReader<ClientNetworkEvent>) {
+fn enter_view(commands: &mut Commands, players: &mut Players, entered_view: &EnteredView) {
+    let EnteredView {
+        player_id,
+        ref name,
+        direction,
+        position,
+    } = *entered_view;
+    log::debug!(
+        "{:?} ({}) came into view at {:?} facing {:?}",
+        player_id,
+        name,
+        position,
+        direction
+    );
+
+    if let Some(player) = players.0.remove(&player_id) {
+        commands.entity(player).despawn_recursive();
+    }
+    let player = insert_player(commands, direction, position);
+    commands.entity(player).insert(DisplayName(name.clone()));
+    players.0.insert(player_id, player);
+}
+
+fn correct_me(
+    commands: &mut Commands,
+    me: Entity,
+    displayed: Option<&Position>,
+    position: Position,
+    direction: Direction,
+    speed: Speed,
+) {
+    // A single step forward can be walked into; anything else snaps into place
+    let walk_animation = match displayed.and_then(|displayed| displayed.step(direction)) {
+        Some(next) if next == position => WalkAnimation::new(speed),
+        _ => WalkAnimation::default(),
+    };
+
+    commands
+        .entity(me)
+        .insert(position)
+        .insert(direction)
+        .insert(walk_animation);
+}
+
+fn handle_network_events(
+    mut commands: Commands,
+    net: Res<NetworkClient>,
+    mut session: ResMut<Session>,
+    mut players: ResMut<Players>,
+    mut remote_steps: ResMut<RemoteSteps>,
+    mut stats: ResMut<NetStats>,
+    mut network_events: EventReader<ClientNetworkEvent>,
+    mut rejections: EventReader<Received<Rejected>>,
+    me_query: Query<Entity, With<Me>>,
+) {
+    for rejection in rejections.iter() {
+        let Rejected(reason) = &**rejection;
+        log::error!("Rejected by server: {}", reason);
+        session.rejected = true;
+        session.reconnect = None;
+    }
+
     for event in network_events.iter() {
         match event {
             ClientNetworkEvent::Connected => {
                 log::info!("Connected.");
+                wire::send(
+                    &net,
+                    &mut stats,
+                    &Hello {
+                        version: PROTOCOL_VERSION,
+                    },
+                );
+                match &session.credentials {
+                    Some(credentials) => wire::send(
+                        &net,
+                        &mut stats,
+                        &Join {
+                            session: session.token,
+                            credentials: credentials.clone(),
+                            name: session.name.clone(),
+                        },
+                    ),
+                    None => log::error!("Connected without credentials to log in with"),
+                }
+            }
+            ClientNetworkEvent::Disconnected | ClientNetworkEvent::Error(_) => {
+                match event {
+                    ClientNetworkEvent::Error(err) => log::warn!("Network error: {:?}", err),
+                    _ => log::warn!("Disconnected."),
+                }
+
+                // Everyone else is stale until the server tells us about them again
+                let me = me_query.single().unwrap();
+                for (_, player) in players.0.drain() {
+                    if player != me {
+                        commands.entity(player).despawn_recursive();
+                    }
+                }
+                remote_steps.clear();
+
+                let was_playing = session.joined || session.reconnect.is_some();
+                session.joined = false;
+                if was_playing && !session.rejected {
+                    session.schedule_reconnect();
+                }
             }
-            _ => {}
         }
     }
 }
 
 fn handle_player_left(
-    mut player_left_events: EventReader<NetworkData<PlayerLeft>>,
+    mut player_left_events: EventReader<Received<PlayerLeft>>,
     mut commands: Commands,
     mut players: ResMut<Players>,
 ) {
-    for network_data in player_left_events.iter() {
-        let PlayerLeft(player_id) = **network_data;
+    for player_left in player_left_events.iter() {
+        let PlayerLeft(player_id) = **player_left;
         if let Some(player) = players.0.remove(&player_id) {
-            commands.entity(player).despawn();
+            commands.entity(player).despawn_recursive();
             log::trace!("{:?} left.", player_id);
         }
     }
@@ -0,0 +1,94 @@
+use bevy::prelude::*;
+
+use crate::{actions::Action, net_stats::NetStats, prediction::Prediction, ui::UiFont};
+
+// Connection numbers in the top left corner, shown and hidden with the Overlay action
+pub struct OverlayPlugin;
+
+impl Plugin for OverlayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_startup_system(setup_overlay.system().after("load_font"))
+            .add_system(toggle_overlay.system())
+            .add_system(update_overlay.system());
+    }
+}
+
+struct OverlayText;
+
+fn setup_overlay(mut commands: Commands, ui_font: Res<UiFont>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(5.0),
+                    top: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                String::new(),
+                TextStyle {
+                    font: ui_font.0.clone(),
+                    font_size: 12.0,
+                    color: Color::WHITE,
+                },
+                Default::default(),
+            ),
+            visible: Visible {
+                is_visible: false,
+                is_transparent: true,
+            },
+            ..Default::default()
+        })
+        .insert(OverlayText);
+}
+
+fn toggle_overlay(actions: Res<Input<Action>>, mut query: Query<&mut Visible, With<OverlayText>>) {
+    if actions.just_pressed(Action::Overlay) {
+        for mut visible in query.iter_mut() {
+            visible.is_visible = !visible.is_visible;
+        }
+    }
+}
+
+fn update_overlay(
+    stats: Res<NetStats>,
+    prediction: Res<Prediction>,
+    mut query: Query<(&mut Text, &Visible), With<OverlayText>>,
+) {
+    for (mut text, visible) in query.iter_mut() {
+        if !visible.is_visible {
+            continue;
+        }
+
+        let rtt = match stats.rtt {
+            Some(rtt) => format!(
+                "{} ms (jitter {} ms)",
+                rtt.as_millis(),
+                stats.jitter.as_millis()
+            ),
+            None => "-".to_string(),
+        };
+        let server_tick = match stats.server_tick {
+            Some(tick) => tick.to_string(),
+            None => "-".to_string(),
+        };
+
+        text.sections[0].value = [
+            format!("RTT  {}", rtt),
+            format!(
+                "Up   {} msg/s, {} B/s",
+                stats.sent.messages, stats.sent.bytes
+            ),
+            format!(
+                "Down {} msg/s, {} B/s",
+                stats.received.messages, stats.received.bytes
+            ),
+            format!("Unacknowledged moves {}", prediction.pending()),
+            format!("Server tick {}", server_tick),
+        ]
+        .join("\n");
+    }
+}
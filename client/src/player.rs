@@ -1,38 +1,47 @@
 use bevy::prelude::*;
-use woods_common::Position;
+use woods_common::{Direction, Position};
 
-use crate::walk_animation::WalkAnimation;
-use crate::{Collide, Direction, TransformOffset};
+use crate::{
+    graphics::TransformOffset, simulation::Collide, ui::UiFont, walk_animation::WalkAnimation,
+};
 
+// Draws players; what they are and where they go is up to the SimulationPlugin
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.init_resource::<PlayerTextureAtlasHandle>()
-            .add_startup_system(load_sprite.system().label("load_sprite"))
-            .add_startup_system(setup_me.system().after("load_sprite"));
+            .add_startup_system(load_sprite.system())
+            .add_system(add_sprites.system())
+            .add_system(update_name_labels.system());
     }
 }
+
+// Every player, remote or not
+pub struct Player;
+
 pub struct Me;
 
+// Name the server knows the player by, shown in a label above its sprite
+pub struct DisplayName(pub String);
+
+struct NameLabel;
+
 #[derive(Bundle)]
 struct PlayerBundle {
-    #[bundle]
-    sprite_sheet: SpriteSheetBundle,
+    player: Player,
     direction: Direction,
     walk_animation: WalkAnimation,
     collide: Collide,
-    transform_offset: TransformOffset,
 }
 
 impl Default for PlayerBundle {
     fn default() -> Self {
         Self {
-            sprite_sheet: SpriteSheetBundle::default(),
+            player: Player,
             direction: Direction::South,
             walk_animation: Default::default(),
             collide: Default::default(),
-            transform_offset: TransformOffset(Transform::from_translation(Vec3::new(19.0 / 2.0, 38.0 / 2.0, 0.0))),
         }
     }
 }
@@ -52,34 +61,71 @@ fn load_sprite(
     *player_texture_atlas_handle = PlayerTextureAtlasHandle(texture_atlas_handle);
 }
 
-fn setup_me(mut commands: Commands, player_texture_atlas_handle: Res<PlayerTextureAtlasHandle>) {
-    commands
-        .spawn_bundle(PlayerBundle {
-            sprite_sheet: SpriteSheetBundle {
-                texture_atlas: player_texture_atlas_handle.clone().0,
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(Me);
+pub fn setup_me(mut commands: Commands) {
+    commands.spawn_bundle(PlayerBundle::default()).insert(Me);
 }
 
-pub fn insert_player(
-    commands: &mut Commands,
-    player_texture_atlas_handle: PlayerTextureAtlasHandle,
-    direction: Direction,
-    position: Position,
-) -> Entity {
+pub fn insert_player(commands: &mut Commands, direction: Direction, position: Position) -> Entity {
     commands
-        .spawn()
-        .insert_bundle(PlayerBundle {
-            sprite_sheet: SpriteSheetBundle {
-                texture_atlas: player_texture_atlas_handle.0,
-                ..Default::default()
-            },
-            ..Default::default()
-        })
+        .spawn_bundle(PlayerBundle::default())
         .insert(direction)
         .insert(position)
         .id()
 }
+
+fn add_sprites(
+    mut commands: Commands,
+    player_texture_atlas_handle: Res<PlayerTextureAtlasHandle>,
+    query: Query<Entity, Added<Player>>,
+) {
+    for player in query.iter() {
+        commands
+            .entity(player)
+            .insert_bundle(SpriteSheetBundle {
+                texture_atlas: player_texture_atlas_handle.0.clone(),
+                ..Default::default()
+            })
+            .insert(TransformOffset(Transform::from_translation(Vec3::new(
+                19.0 / 2.0,
+                38.0 / 2.0,
+                0.0,
+            ))));
+    }
+}
+
+fn update_name_labels(
+    mut commands: Commands,
+    ui_font: Res<UiFont>,
+    query: Query<(Entity, &DisplayName, Option<&Children>), Changed<DisplayName>>,
+    label_query: Query<(), With<NameLabel>>,
+) {
+    for (player, DisplayName(name), children) in query.iter() {
+        for child in children.iter().flat_map(|children| children.iter()) {
+            if label_query.get(*child).is_ok() {
+                commands.entity(*child).despawn();
+            }
+        }
+
+        // A child of the sprite so it follows both the walk animation and TransformOffset
+        let label = commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::with_section(
+                    name.clone(),
+                    TextStyle {
+                        font: ui_font.0.clone(),
+                        font_size: 12.0,
+                        color: Color::WHITE,
+                    },
+                    TextAlignment {
+                        vertical: VerticalAlign::Bottom,
+                        horizontal: HorizontalAlign::Center,
+                    },
+                ),
+                transform: Transform::from_xyz(0.0, 22.0, 0.5),
+                ..Default::default()
+            })
+            .insert(NameLabel)
+            .id();
+        commands.entity(player).push_children(&[label]);
+    }
+}
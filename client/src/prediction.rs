@@ -0,0 +1,78 @@
+use std::collections::VecDeque;
+
+use woods_common::{Direction, Map, Position};
+
+// Where a move input leaves a player, following the same rules as the server
+pub fn predict(
+    map: &Map,
+    from: Position,
+    facing: Direction,
+    direction: Direction,
+) -> (Position, u16) {
+    if facing != direction {
+        // Turning (i.e. changing directions) requires its own keydown
+        return (from, 0);
+    }
+
+    match map.step(&from, direction) {
+        Some(to) => (to, 1),
+        None => (from, 0),
+    }
+}
+
+// Inputs sent to the server but not yet acknowledged by a MoveUpdate
+#[derive(Default)]
+pub struct Prediction {
+    next_sequence: u32,
+    pending: VecDeque<(u32, Direction)>,
+    // Latest predicted state of Me; commands may not have applied it to the entity yet
+    predicted: Option<(Position, Direction)>,
+}
+
+impl Prediction {
+    pub fn reset(&mut self, position: Position, direction: Direction) {
+        self.pending.clear();
+        self.predicted = Some((position, direction));
+    }
+
+    // Inputs the server hasn't acknowledged yet
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    // Records a predicted move and returns the sequence to send with it
+    pub fn push(&mut self, direction: Direction, position: Position) -> u32 {
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        self.pending.push_back((self.next_sequence, direction));
+        self.predicted = Some((position, direction));
+        self.next_sequence
+    }
+
+    // Rewinds to the server's state as of `sequence` and replays the inputs it has not seen yet,
+    // returning the new state if it differs from what was predicted
+    pub fn reconcile(
+        &mut self,
+        map: &Map,
+        sequence: u32,
+        position: Position,
+        direction: Direction,
+    ) -> Option<(Position, Direction)> {
+        while matches!(self.pending.front(), Some((pending, _)) if *pending <= sequence) {
+            self.pending.pop_front();
+        }
+
+        let replayed = self.pending.iter().fold(
+            (position, direction),
+            |(position, facing), (_, direction)| {
+                (predict(map, position, facing, *direction).0, *direction)
+            },
+        );
+
+        if self.predicted == Some(replayed) {
+            None
+        } else {
+            self.predicted = Some(replayed);
+            Some(replayed)
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use bevy::prelude::*;
+use bevy_spicy_networking::NetworkClient;
+
+use woods_common::{Direction, Map, MoveInput, Position, Speed};
+
+use crate::{
+    interpolation::InterpolationPlugin,
+    map::load_map,
+    net_stats::{NetStats, NetStatsPlugin},
+    network::{NetworkPlugin, Session},
+    player::{setup_me, Me},
+    prediction::{predict, Prediction},
+    walk_animation::{advance_walks, WalkAnimation},
+    wire,
+};
+
+// The game as the client sees it: the connection, every player in view and where they are
+// going. Needs nothing beyond MinimalPlugins, so it also runs without a window; drawing is left
+// to the GraphicsPlugin and input to whoever sends WalkEvents.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // Headless users may bring their own map
+        if !app.world().contains_resource::<Map>() {
+            app.insert_resource(load_map());
+        }
+
+        app.add_plugin(NetworkPlugin)
+            .add_plugin(InterpolationPlugin)
+            .add_plugin(NetStatsPlugin)
+            .add_event::<WalkEvent>()
+            .add_startup_system(setup_me.system())
+            .add_system(walk.system().label("walk"))
+            .add_system(advance_walks.system().label("advance_walks"));
+    }
+}
+
+pub struct WalkEvent {
+    pub player: Entity,
+    pub me: bool,
+    pub direction: Direction,
+    pub to: Position,
+    pub distance: u16,
+    pub speed: Speed,
+}
+
+impl WalkEvent {
+    pub fn from(
+        map: &Map,
+        player: Entity,
+        me: bool,
+        previous_direction: Direction,
+        direction: Direction,
+        from: Position,
+        speed: Speed,
+    ) -> Self {
+        let (to, distance) = predict(map, from, previous_direction, direction);
+
+        WalkEvent {
+            player,
+            me,
+            direction,
+            to,
+            distance,
+            speed,
+        }
+    }
+
+    fn should_animate(&self) -> bool {
+        self.distance > 0
+    }
+}
+
+#[derive(Default)]
+pub struct Collide;
+
+fn walk(
+    mut walk_events: EventReader<WalkEvent>,
+    net: Res<NetworkClient>,
+    mut stats: ResMut<NetStats>,
+    session: Res<Session>,
+    mut prediction: ResMut<Prediction>,
+    mut commands: Commands,
+    query: Query<(&Collide, &Position), Without<Me>>,
+) {
+    for walk_event in walk_events.iter() {
+        if walk_event.me {
+            // Moves can't reach the server until it has let us back in
+            if !session.joined() {
+                continue;
+            }
+
+            let collision = query.iter().any(|(_, position)| *position == walk_event.to);
+            if collision {
+                log::trace!("Ignoring move attempt due to collision");
+                continue;
+            }
+        }
+
+        let mut entity_commands = commands.entity(walk_event.player);
+
+        entity_commands
+            .insert(walk_event.direction)
+            .insert(walk_event.to);
+
+        if walk_event.should_animate() {
+            entity_commands.insert(WalkAnimation::new(walk_event.speed));
+        }
+
+        if walk_event.me {
+            let sequence = prediction.push(walk_event.direction, walk_event.to);
+            wire::send(
+                &net,
+                &mut stats,
+                &MoveInput {
+                    sequence,
+                    direction: walk_event.direction,
+                    position: walk_event.to,
+                    speed: walk_event.speed,
+                },
+            );
+        }
+    }
+}
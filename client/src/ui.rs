@@ -0,0 +1,22 @@
+use bevy::prelude::*;
+
+pub struct UiPlugin;
+
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<UiFont>()
+            .add_startup_system(load_font.system().label("load_font"))
+            .add_startup_system(setup_ui_camera.system());
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct UiFont(pub Handle<Font>);
+
+fn load_font(asset_server: Res<AssetServer>, mut ui_font: ResMut<UiFont>) {
+    *ui_font = UiFont(asset_server.load("fonts/DejaVuSansMono.ttf"));
+}
+
+fn setup_ui_camera(mut commands: Commands) {
+    commands.spawn_bundle(UiCameraBundle::default());
+}
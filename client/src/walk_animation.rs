@@ -1,12 +1,16 @@
 use bevy::prelude::*;
 use std::time::Duration;
-use woods_common::{Direction, Position};
+use woods_common::{Direction, Position, Speed};
 
 const TILE_SIZE: f32 = 20.0;
 const STEP_DIST: f32 = TILE_SIZE / 3.0;
 const FRAMES_PER_DIRECTION: u32 = 6;
 
-const WALK_DURATION: Duration = Duration::from_millis(300 / 3);
+// Each direction's frames hold a walk cycle followed by a run cycle
+const RUN_FRAMES_OFFSET: u32 = 3;
+
+// Stages in a step that take time; Stop doesn't
+const STAGES_PER_STEP: u32 = 3;
 
 #[derive(PartialEq, Eq, Copy, Clone)]
 enum WalkStage {
@@ -47,6 +51,9 @@ impl WalkStage {
 
 pub struct WalkAnimation {
     stage: WalkStage,
+    speed: Speed,
+    // How many times faster than normal the stages go by
+    rate: f32,
     timer: Option<Timer>,
 }
 
@@ -55,20 +62,31 @@ impl WalkAnimation {
         self.timer.is_some()
     }
 
-    pub fn new() -> Self {
+    pub fn new(speed: Speed) -> Self {
         Self {
             stage: WalkStage::Step1,
-            timer: Some(Timer::new(WALK_DURATION, false)),
+            speed,
+            rate: 1.0,
+            timer: Some(Timer::new(stage_duration(speed), false)),
         }
     }
 
     pub fn sprite_index_offset(&self) -> u32 {
-        self.stage.sprite_index_offset()
+        match self.speed {
+            Speed::Run if self.stage != WalkStage::Stop => {
+                RUN_FRAMES_OFFSET + self.stage.sprite_index_offset()
+            }
+            _ => self.stage.sprite_index_offset(),
+        }
+    }
+
+    pub fn hurry(&mut self, rate: f32) {
+        self.rate = rate;
     }
 
     pub fn tick(&mut self, duration: Duration) {
         if let Some(ref mut timer) = self.timer {
-            timer.tick(duration);
+            timer.tick(duration.mul_f32(self.rate));
             if timer.finished() {
                 self.next();
             }
@@ -80,7 +98,7 @@ impl WalkAnimation {
         if self.stage == WalkStage::Stop {
             self.timer = None;
         } else {
-            self.timer = Some(Timer::new(WALK_DURATION, false))
+            self.timer = Some(Timer::new(stage_duration(self.speed), false))
         }
     }
 
@@ -95,24 +113,35 @@ impl Default for WalkAnimation {
     fn default() -> Self {
         Self {
             stage: WalkStage::Stop,
+            speed: Speed::Walk,
+            rate: 1.0,
             timer: None,
         }
     }
 }
 
+fn stage_duration(speed: Speed) -> Duration {
+    speed.step_duration() / STAGES_PER_STEP
+}
+
+// Steps take time whether or not anything is drawn, and players can't take another until the
+// last one is done
+pub fn advance_walks(time: Res<Time>, mut query: Query<&mut WalkAnimation>) {
+    for mut walk_animation in query.iter_mut() {
+        walk_animation.tick(time.delta());
+    }
+}
+
 pub fn walk_animation(
-    time: Res<Time>,
     mut query: Query<(
         &mut TextureAtlasSprite,
         &Direction,
-        &mut WalkAnimation,
+        &WalkAnimation,
         &Position,
         &mut Transform,
     )>,
 ) {
-    for (mut sprite, direction, mut walk_animation, position, mut transform) in query.iter_mut() {
-        walk_animation.tick(time.delta());
-
+    for (mut sprite, direction, walk_animation, position, mut transform) in query.iter_mut() {
         let sprite_index_offset = FRAMES_PER_DIRECTION
             * match direction {
                 Direction::North => 0,
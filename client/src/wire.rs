@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+use bevy_spicy_networking::{AppNetworkClientMessage, NetworkClient, NetworkData};
+use std::ops::Deref;
+
+use woods_common::{
+    wire::{self, DECODE_STAGE},
+    Packet, WireMessage,
+};
+
+use crate::net_stats::NetStats;
+
+pub struct WirePlugin;
+
+impl Plugin for WirePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_stage_after(CoreStage::PreUpdate, DECODE_STAGE, SystemStage::parallel())
+            .listen_for_client_message::<Packet>();
+    }
+}
+
+// A decoded message from the server, read through EventReader<Received<T>>
+pub struct Received<T>(T);
+
+impl<T> Deref for Received<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+pub trait AppWireMessage {
+    fn listen_for_wire_message<T: WireMessage>(&mut self) -> &mut Self;
+}
+
+impl AppWireMessage for AppBuilder {
+    fn listen_for_wire_message<T: WireMessage>(&mut self) -> &mut Self {
+        self.add_event::<Received<T>>()
+            .add_system_to_stage(DECODE_STAGE, decode_packets::<T>.system())
+    }
+}
+
+fn decode_packets<T: WireMessage>(
+    mut packets: EventReader<NetworkData<Packet>>,
+    mut received: EventWriter<Received<T>>,
+) {
+    for packet in packets.iter() {
+        if wire::message_id(packet) != Some(T::ID) {
+            continue;
+        }
+
+        match wire::decode::<T>(packet) {
+            Ok(message) => received.send(Received(message)),
+            Err(err) => log::warn!("Malformed {} from server: {}", T::NAME, err),
+        }
+    }
+}
+
+// Counts what was sent in `stats`, so the overlay can show the traffic going up
+pub fn send<T: WireMessage>(net: &NetworkClient, stats: &mut NetStats, message: &T) {
+    let packet = wire::encode(message);
+    let bytes = packet.0.len();
+    match net.send_message(packet) {
+        Ok(()) => stats.count_sent(bytes),
+        Err(err) => log::warn!("Could not send {}: {}", T::NAME, err),
+    }
+}
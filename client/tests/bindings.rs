@@ -0,0 +1,23 @@
+// Reading bindings.toml, without starting the game
+
+use std::{fs, time::Duration};
+
+use woods_client::{actions::Bindings, map::assets_dir};
+
+#[test]
+fn ships_the_default_bindings() {
+    let path = assets_dir().join("bindings.toml");
+    assert_eq!(Bindings::load(&path), Ok(Bindings::default()));
+}
+
+#[test]
+fn reads_the_turn_threshold() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bindings.toml");
+    fs::write(&path, "turn_threshold_ms = 250\n").unwrap();
+
+    let bindings = Bindings::load(&path).unwrap();
+    assert_eq!(bindings.turn_threshold(), Duration::from_millis(250));
+    // Everything left out keeps its default
+    assert_eq!(bindings.move_north, Bindings::default().move_north);
+}
@@ -0,0 +1,226 @@
+// The client's SimulationPlugin on MinimalPlugins, against a server in the same process
+
+use bevy::{app::ManualEventReader, prelude::*};
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use woods_client::{
+    actions::{Action, Bindings},
+    chat::ChatInput,
+    connect::Connect,
+    controls::ControlsPlugin,
+    map::load_map,
+    player::Me,
+    simulation::{SimulationPlugin, WalkEvent},
+};
+use woods_common::{Credentials, Direction, Map, PlayerId, Position, Speed};
+use woods_server::{
+    persistence::{PlayerStore, SavedPlayer},
+    testing::{TestServer, PASSWORD},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn run_until(
+    server: &mut App,
+    client: &mut App,
+    what: &str,
+    mut done: impl FnMut(&mut App, &mut App) -> bool,
+) {
+    let start = Instant::now();
+    while !done(server, client) {
+        if start.elapsed() > TIMEOUT {
+            panic!("Timed out waiting for {}", what);
+        }
+        server.update();
+        client.update();
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+// A client logging in as alice, with whatever else `configure` adds
+fn client(address: SocketAddr, configure: impl FnOnce(&mut AppBuilder)) -> App {
+    let mut builder = App::build();
+    builder
+        .add_plugins(MinimalPlugins)
+        .add_plugin(SimulationPlugin);
+    configure(&mut builder);
+    let mut client = builder.app;
+    client
+        .world
+        .get_resource_mut::<Events<Connect>>()
+        .unwrap()
+        .send(Connect {
+            address,
+            credentials: Credentials::Password {
+                username: "alice".to_string(),
+                password: PASSWORD.to_string(),
+            },
+            name: "alice".to_string(),
+        });
+    client
+}
+
+fn me(client: &mut App) -> Option<(Entity, PlayerId, Position, Direction)> {
+    let mut query = client
+        .world
+        .query_filtered::<(Entity, &PlayerId, &Position, &Direction), With<Me>>();
+    query
+        .iter(&client.world)
+        .next()
+        .map(|(entity, player_id, position, direction)| (entity, *player_id, *position, *direction))
+}
+
+fn server_position(server: &mut App, player_id: PlayerId) -> Position {
+    let mut query = server.world.query::<(&PlayerId, &Position)>();
+    query
+        .iter(&server.world)
+        .find(|(other, _)| **other == player_id)
+        .map(|(_, position)| *position)
+        .unwrap()
+}
+
+#[test]
+fn joins_and_walks_without_a_window() {
+    let TestServer {
+        app: mut server,
+        address,
+        dir: _dir,
+    } = TestServer::new(&["alice"]);
+
+    let mut client = client(address, |_| {});
+    run_until(&mut server, &mut client, "a welcome", |_, client| {
+        me(client).is_some()
+    });
+    let (entity, player_id, start, facing) = me(&mut client).unwrap();
+
+    // Turns to face open ground if need be, then takes a step, as the controls would
+    let map = client.world.get_resource::<Map>().unwrap();
+    let direction = [
+        Direction::North,
+        Direction::South,
+        Direction::East,
+        Direction::West,
+    ]
+    .iter()
+    .copied()
+    .find(|direction| map.step(&start, *direction).is_some())
+    .expect("alice spawned boxed in");
+    let mut events = Vec::new();
+    if direction != facing {
+        events.push(WalkEvent::from(
+            map,
+            entity,
+            true,
+            facing,
+            direction,
+            start,
+            Speed::Walk,
+        ));
+    }
+    events.push(WalkEvent::from(
+        map,
+        entity,
+        true,
+        direction,
+        direction,
+        start,
+        Speed::Walk,
+    ));
+    let destination = events.last().unwrap().to;
+    let mut walk_events = client
+        .world
+        .get_resource_mut::<Events<WalkEvent>>()
+        .unwrap();
+    for event in events {
+        walk_events.send(event);
+    }
+
+    run_until(
+        &mut server,
+        &mut client,
+        "the server to agree",
+        |server, client| {
+            server_position(server, player_id) == destination
+                && me(client).map(|(_, _, position, _)| position) == Some(destination)
+        },
+    );
+}
+
+#[test]
+fn holding_into_a_wall_sends_nothing() {
+    // Alice was last seen somewhere she can't step forward from, facing that way
+    let map = load_map();
+    let directions = [
+        Direction::North,
+        Direction::South,
+        Direction::East,
+        Direction::West,
+    ];
+    let (start, direction) = (0..map.width)
+        .flat_map(|x| (0..map.height).map(move |y| Position { x, y }))
+        .filter(|position| map.is_walkable(position))
+        .flat_map(|position| {
+            directions
+                .iter()
+                .map(move |direction| (position, *direction))
+        })
+        .find(|(position, direction)| map.step(position, *direction).is_none())
+        .expect("the map has nowhere to walk into");
+
+    let TestServer {
+        app: mut server,
+        address,
+        dir: _dir,
+    } = TestServer::with_config(&["alice"], |config| {
+        let mut store = PlayerStore::load(&config.store).unwrap();
+        store.update(
+            "alice",
+            SavedPlayer {
+                position: start,
+                direction,
+            },
+        );
+        store.save();
+    });
+
+    let mut client = client(address, |builder| {
+        builder
+            .init_resource::<Bindings>()
+            .init_resource::<Input<Action>>()
+            .init_resource::<ChatInput>()
+            .add_plugin(ControlsPlugin);
+    });
+    run_until(&mut server, &mut client, "a welcome", |_, client| {
+        me(client).is_some()
+    });
+    let (_, _, position, facing) = me(&mut client).unwrap();
+    assert_eq!((position, facing), (start, direction));
+
+    // Pressed once and then held well past the turn threshold
+    client
+        .world
+        .get_resource_mut::<Input<Action>>()
+        .unwrap()
+        .press(Action::Move(direction));
+    let mut reader = ManualEventReader::<WalkEvent>::default();
+    let mut sent = 0;
+    let held = Instant::now();
+    while held.elapsed() < Duration::from_millis(500) {
+        server.update();
+        client.update();
+        client
+            .world
+            .get_resource_mut::<Input<Action>>()
+            .unwrap()
+            .clear();
+        let walk_events = client.world.get_resource::<Events<WalkEvent>>().unwrap();
+        sent += reader.iter(walk_events).filter(|event| event.me).count();
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    assert_eq!(sent, 0);
+}
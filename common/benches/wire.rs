@@ -0,0 +1,178 @@
+// Compares the wire format with the typetag encoding used up to protocol version 7, where
+// bevy_spicy_networking sent each message as a bincode-encoded { kind, data } pair with the
+// message's typetag name repeated inside `data`.
+//
+//     cargo bench -p woods-common --bench wire
+
+use bevy_spicy_networking::NetworkMessage;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serde::{Deserialize, Serialize};
+
+use woods_common::{
+    wire, Direction, EnteredView, MoveUpdate, Packet, PlayerId, Position, Snapshot, Speed,
+};
+
+// Snapshot and its parts as they were in version 7, before moves had a speed. It was registered
+// under typetag's default name, with the woods: prefix only on the envelope's kind.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct LegacyMoveUpdate {
+    player_id: PlayerId,
+    direction: Direction,
+    position: Position,
+    distance: u16,
+    sequence: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct LegacyEnteredView {
+    player_id: PlayerId,
+    name: String,
+    direction: Direction,
+    position: Position,
+    sequence: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct LegacySnapshot {
+    tick: u32,
+    entered: Vec<LegacyEnteredView>,
+    moves: Vec<LegacyMoveUpdate>,
+    left: Vec<PlayerId>,
+}
+
+#[typetag::serde(name = "Snapshot")]
+impl NetworkMessage for LegacySnapshot {}
+
+impl From<&Snapshot> for LegacySnapshot {
+    fn from(snapshot: &Snapshot) -> Self {
+        LegacySnapshot {
+            tick: snapshot.tick,
+            entered: snapshot
+                .entered
+                .iter()
+                .map(|entered| LegacyEnteredView {
+                    player_id: entered.player_id,
+                    name: entered.name.clone(),
+                    direction: entered.direction,
+                    position: entered.position,
+                    // No longer sent; bincode writes the same four bytes whatever it is
+                    sequence: 0,
+                })
+                .collect(),
+            moves: snapshot
+                .moves
+                .iter()
+                .map(|update| LegacyMoveUpdate {
+                    player_id: update.player_id,
+                    direction: update.direction,
+                    position: update.position,
+                    distance: update.distance,
+                    sequence: update.sequence,
+                })
+                .collect(),
+            left: snapshot.left.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    kind: String,
+    data: Box<dyn NetworkMessage>,
+}
+
+// One tick in a busy area: a player walks into view while a dozen others move around
+fn snapshot() -> Snapshot {
+    Snapshot {
+        tick: 123_456,
+        entered: vec![EnteredView {
+            player_id: PlayerId(42),
+            name: "Wanderer".to_string(),
+            direction: Direction::South,
+            position: Position { x: 310, y: 95 },
+        }],
+        moves: (0..12)
+            .map(|i| MoveUpdate {
+                player_id: PlayerId(i),
+                direction: Direction::East,
+                position: Position {
+                    x: 300 + i as u16,
+                    y: 100,
+                },
+                distance: 1,
+                speed: Speed::Walk,
+                sequence: 5_000 + i,
+            })
+            .collect(),
+        left: vec![PlayerId(7)],
+    }
+}
+
+fn legacy_encode(snapshot: &LegacySnapshot) -> Vec<u8> {
+    bincode::serialize(&Envelope {
+        kind: "woods:Snapshot".to_string(),
+        data: Box::new(snapshot.clone()),
+    })
+    .unwrap()
+}
+
+fn wire_encode(snapshot: &Snapshot) -> Vec<u8> {
+    bincode::serialize(&Envelope {
+        kind: "p".to_string(),
+        data: Box::new(wire::encode(snapshot)),
+    })
+    .unwrap()
+}
+
+fn report_sizes(snapshot: &Snapshot) {
+    let updates = snapshot.entered.len() + snapshot.moves.len() + snapshot.left.len();
+    for (format, bytes) in &[
+        ("typetag", legacy_encode(&snapshot.into()).len()),
+        ("wire", wire_encode(snapshot).len()),
+        ("wire payload", wire::encode(snapshot).0.len()),
+    ] {
+        println!(
+            "{:>12}: {} bytes per snapshot, {:.1} bytes per update",
+            format,
+            bytes,
+            *bytes as f64 / updates as f64
+        );
+    }
+}
+
+fn bench(c: &mut Criterion) {
+    let snapshot = snapshot();
+    report_sizes(&snapshot);
+
+    let legacy_snapshot = LegacySnapshot::from(&snapshot);
+    let legacy = legacy_encode(&legacy_snapshot);
+    let packet = wire::encode(&snapshot);
+
+    let mut group = c.benchmark_group("snapshot");
+    group.bench_function("typetag encode", |b| {
+        b.iter(|| legacy_encode(black_box(&legacy_snapshot)))
+    });
+    group.bench_function("wire encode", |b| {
+        b.iter(|| wire::encode(black_box(&snapshot)))
+    });
+    group.bench_function("typetag decode", |b| {
+        b.iter(|| bincode::deserialize::<Envelope>(black_box(&legacy)).unwrap())
+    });
+    group.bench_function("wire decode", |b| {
+        b.iter(|| wire::decode::<Snapshot>(black_box(&packet)).unwrap())
+    });
+    group.finish();
+
+    // What bevy_spicy_networking itself does around the payload, for a fair total
+    let framed = wire_encode(&snapshot);
+    c.bench_function("snapshot/wire decode framed", |b| {
+        b.iter(|| {
+            let envelope = bincode::deserialize::<Envelope>(black_box(&framed)).unwrap();
+            let packet = envelope.data.downcast_ref::<Packet>().unwrap();
+            wire::decode::<Snapshot>(packet).unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
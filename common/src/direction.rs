@@ -1,9 +1,8 @@
 use bevy::prelude::*;
 
 use serde::{Deserialize, Serialize};
-use std::convert::TryFrom;
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Copy, Clone)]
+#[derive(Hash, Serialize, Deserialize, Debug, Eq, PartialEq, Copy, Clone)]
 pub enum Direction {
     North,
     South,
@@ -17,20 +16,6 @@ impl Default for Direction {
     }
 }
 
-impl TryFrom<KeyCode> for Direction {
-    type Error = &'static str;
-
-    fn try_from(key_code: KeyCode) -> Result<Self, Self::Error> {
-        match key_code {
-            KeyCode::Right => Ok(Direction::East),
-            KeyCode::Left => Ok(Direction::West),
-            KeyCode::Up => Ok(Direction::North),
-            KeyCode::Down => Ok(Direction::South),
-            _ => Err("Not a direction key"),
-        }
-    }
-}
-
 impl Direction {
     pub fn translation(&self) -> Vec2 {
         match self {
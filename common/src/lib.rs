@@ -1,23 +1,78 @@
 pub mod direction;
+pub mod map;
+pub mod name;
+pub mod wire;
 
 use bevy::math::Vec2;
 
-use bevy_spicy_networking::{ClientMessage, NetworkMessage, ServerMessage};
 pub use direction::Direction;
+pub use map::Map;
+pub use name::{validate_name, MAX_NAME_LENGTH};
+pub use wire::{Packet, WireMessage};
 
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 pub const SERVER_PORT: u16 = 14192;
 
+// Bump whenever a message changes shape; the server only accepts clients with the same version
+pub const PROTOCOL_VERSION: u32 = 12;
+
+// The server simulates the world and sends snapshots this many times per second
+pub const TICK_RATE: f64 = 20.0;
+
+// Longest chat message in characters; the server truncates anything longer
+pub const MAX_CHAT_LENGTH: usize = 200;
+
 #[derive(Hash, Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
 pub struct PlayerId(pub u32);
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+// Issued in Welcome; presenting it in Join after a dropped connection resumes the same player
+#[derive(Hash, Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SessionToken(pub u64);
+
+#[derive(Hash, Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
 pub struct Position {
     pub x: u16,
     pub y: u16,
 }
 
+impl Position {
+    // The neighbouring tile in `direction`, or None if it would fall outside the u16 grid
+    pub fn step(&self, direction: Direction) -> Option<Position> {
+        let Position { x, y } = *self;
+        match direction {
+            Direction::North => y.checked_add(1).map(|y| Position { x, y }),
+            Direction::South => y.checked_sub(1).map(|y| Position { x, y }),
+            Direction::East => x.checked_add(1).map(|x| Position { x, y }),
+            Direction::West => x.checked_sub(1).map(|x| Position { x, y }),
+        }
+    }
+}
+
+// How quickly a player takes a step. Clients pace animations by it and the server won't let
+// anyone step sooner than it allows.
+#[derive(Hash, Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Speed {
+    Walk,
+    Run,
+}
+
+impl Default for Speed {
+    fn default() -> Self {
+        Speed::Walk
+    }
+}
+
+impl Speed {
+    pub fn step_duration(&self) -> Duration {
+        match self {
+            Speed::Walk => Duration::from_millis(300),
+            Speed::Run => Duration::from_millis(150),
+        }
+    }
+}
+
 impl From<Position> for Vec2 {
     fn from(position: Position) -> Self {
         Self::new(position.x.into(), position.y.into())
@@ -26,26 +81,120 @@ impl From<Position> for Vec2 {
 
 // Client -> Server messages
 
+// First message on every connection. Hello and Rejected are exchanged before either side knows
+// the other speaks the same protocol, so their shape and IDs must never change. Before version 8
+// they weren't sent in a Packet; see wire::LegacyHello.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hello {
+    pub version: u32,
+}
+
+impl WireMessage for Hello {
+    const ID: u8 = 0;
+    const NAME: &'static str = "Hello";
+}
+
+// Proves which account a player belongs to
+#[derive(Serialize, Deserialize, Clone)]
+pub enum Credentials {
+    Password { username: String, password: String },
+    // Pre-issued by the server operator, in the form username:secret
+    Token(String),
+}
+
+impl Credentials {
+    pub fn username(&self) -> &str {
+        match self {
+            Credentials::Password { username, .. } => username,
+            Credentials::Token(token) => token.split(':').next().unwrap_or_default(),
+        }
+    }
+}
+
+// Keeps secrets out of logs
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Credentials::Password { username, .. } => write!(f, "Password({:?})", username),
+            Credentials::Token(_) => write!(f, "Token({:?})", self.username()),
+        }
+    }
+}
+
+// Sent straight after Hello to log in and ask for a player
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Join {
+    pub session: Option<SessionToken>,
+    pub credentials: Credentials,
+    // What the player would like to be called; the server may adjust it to keep names unique
+    pub name: String,
+}
+
+impl WireMessage for Join {
+    const ID: u8 = 2;
+    const NAME: &'static str = "Join";
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct MoveInput(pub Direction, pub Position);
+pub struct MoveInput {
+    // Increases with every input so the server can acknowledge them in MoveUpdate
+    pub sequence: u32,
+    pub direction: Direction,
+    // Where the client predicts the move will leave it
+    pub position: Position,
+    pub speed: Speed,
+}
 
-#[typetag::serde]
-impl NetworkMessage for MoveInput {}
+impl WireMessage for MoveInput {
+    const ID: u8 = 3;
+    const NAME: &'static str = "MoveInput";
+}
 
-impl ServerMessage for MoveInput {
-    const NAME: &'static str = "woods:MoveInput";
+// Something the player typed into the chat box
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Say(pub String);
+
+impl WireMessage for Say {
+    const ID: u8 = 4;
+    const NAME: &'static str = "Say";
+}
+
+// Sent every so often to measure the round trip; the server answers each with a Pong
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Ping {
+    pub sequence: u32,
+    // The last round trip the client measured, in milliseconds, for the server's logs
+    pub rtt: Option<u32>,
+}
+
+impl WireMessage for Ping {
+    const ID: u8 = 5;
+    const NAME: &'static str = "Ping";
 }
 
 // Server -> Client messages
 
+// Human-readable reason the server is about to close the connection
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Welcome(pub PlayerId, pub Position);
+pub struct Rejected(pub String);
+
+impl WireMessage for Rejected {
+    const ID: u8 = 1;
+    const NAME: &'static str = "Rejected";
+}
 
-#[typetag::serde]
-impl NetworkMessage for Welcome {}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Welcome {
+    pub player_id: PlayerId,
+    pub position: Position,
+    pub direction: Direction,
+    pub session: SessionToken,
+    pub name: String,
+}
 
-impl ClientMessage for Welcome {
-    const NAME: &'static str = "woods:Welcome";
+impl WireMessage for Welcome {
+    const ID: u8 = 16;
+    const NAME: &'static str = "Welcome";
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -54,21 +203,61 @@ pub struct MoveUpdate {
     pub direction: Direction,
     pub position: Position,
     pub distance: u16,
+    pub speed: Speed,
+    // Last MoveInput sequence the server processed for this player
+    pub sequence: u32,
 }
 
-#[typetag::serde]
-impl NetworkMessage for MoveUpdate {}
+// Another player came within view; MoveUpdates about it follow until it leaves
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EnteredView {
+    pub player_id: PlayerId,
+    pub name: String,
+    pub direction: Direction,
+    pub position: Position,
+}
+
+// Everything a client needs to hear about from one server tick, applied in field order
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    pub tick: u32,
+    pub entered: Vec<EnteredView>,
+    pub moves: Vec<MoveUpdate>,
+    // Players that moved out of view; they are still in the world
+    pub left: Vec<PlayerId>,
+}
 
-impl ClientMessage for MoveUpdate {
-    const NAME: &'static str = "woods:MoveInfo";
+impl WireMessage for Snapshot {
+    const ID: u8 = 17;
+    const NAME: &'static str = "Snapshot";
 }
 
+// The player left the world
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PlayerLeft(pub PlayerId);
 
-#[typetag::serde]
-impl NetworkMessage for PlayerLeft {}
+impl WireMessage for PlayerLeft {
+    const ID: u8 = 18;
+    const NAME: &'static str = "PlayerLeft";
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Chat {
+    // None for notices from the server itself
+    pub player_id: Option<PlayerId>,
+    pub text: String,
+}
+
+impl WireMessage for Chat {
+    const ID: u8 = 19;
+    const NAME: &'static str = "Chat";
+}
+
+// Echoes the sequence of a Ping
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Pong(pub u32);
 
-impl ClientMessage for PlayerLeft {
-    const NAME: &'static str = "woods:PlayerLeft";
+impl WireMessage for Pong {
+    const ID: u8 = 20;
+    const NAME: &'static str = "Pong";
 }
@@ -0,0 +1,349 @@
+use std::{
+    collections::HashSet,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{Direction, Position};
+
+// Tiled stores flip/rotation flags in the high bits of each gid
+const GID_FLAGS: u32 = 0xE000_0000;
+
+// Object layer whose objects mark the tiles players may spawn on
+const SPAWN_LAYER: &str = "spawn";
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error),
+    Xml(roxmltree::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Io(err) => write!(f, "{}", err),
+            MapError::Xml(err) => write!(f, "{}", err),
+            MapError::Invalid(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl From<io::Error> for MapError {
+    fn from(err: io::Error) -> Self {
+        MapError::Io(err)
+    }
+}
+
+impl From<roxmltree::Error> for MapError {
+    fn from(err: roxmltree::Error) -> Self {
+        MapError::Xml(err)
+    }
+}
+
+pub struct Tileset {
+    pub first_gid: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub columns: u32,
+    pub tile_count: u32,
+    // Relative to the directory of the map file
+    pub image: PathBuf,
+    // Local tile ids with a `solid` property set to true
+    solid: HashSet<u32>,
+}
+
+pub struct Layer {
+    pub name: String,
+    // Row-major gids, starting from the top-left tile as Tiled does
+    pub tiles: Vec<u32>,
+}
+
+// A rectangle of tiles, with `origin` as its bottom-left corner
+#[derive(Debug, Clone, Copy)]
+pub struct SpawnZone {
+    pub origin: Position,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl SpawnZone {
+    pub fn positions(&self) -> impl Iterator<Item = Position> {
+        let SpawnZone {
+            origin,
+            width,
+            height,
+        } = *self;
+        (0..height).flat_map(move |dy| {
+            (0..width).map(move |dx| Position {
+                x: origin.x + dx,
+                y: origin.y + dy,
+            })
+        })
+    }
+}
+
+pub struct Map {
+    pub width: u16,
+    pub height: u16,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tilesets: Vec<Tileset>,
+    pub layers: Vec<Layer>,
+    pub spawn_zones: Vec<SpawnZone>,
+    // Indexed like layer tiles
+    solid: Vec<bool>,
+}
+
+impl Map {
+    pub fn load(path: &Path) -> Result<Self, MapError> {
+        let text = fs::read_to_string(path)?;
+        let document = roxmltree::Document::parse(&text)?;
+        let root = document.root_element();
+        let map_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        if root.attribute("infinite") == Some("1") {
+            return Err(MapError::Invalid("infinite maps are not supported".into()));
+        }
+
+        let width: u16 = attribute(&root, "width")?;
+        let height: u16 = attribute(&root, "height")?;
+        let tile_width: u32 = attribute(&root, "tilewidth")?;
+        let tile_height: u32 = attribute(&root, "tileheight")?;
+        let tile_count = width as usize * height as usize;
+
+        let mut tilesets = Vec::new();
+        let mut layers = Vec::new();
+        let mut spawn_objects = Vec::new();
+
+        for node in root.children().filter(|n| n.is_element()) {
+            match node.tag_name().name() {
+                "tileset" => {
+                    let first_gid: u32 = attribute(&node, "firstgid")?;
+                    let tileset = match node.attribute("source") {
+                        Some(source) => {
+                            let tsx_path = map_dir.join(source);
+                            let tsx_text = fs::read_to_string(&tsx_path)?;
+                            let tsx = roxmltree::Document::parse(&tsx_text)?;
+                            // Image paths in a tsx are relative to the tsx itself
+                            let tsx_dir =
+                                Path::new(source).parent().unwrap_or_else(|| Path::new(""));
+                            parse_tileset(&tsx.root_element(), first_gid, tsx_dir)?
+                        }
+                        None => parse_tileset(&node, first_gid, Path::new(""))?,
+                    };
+                    tilesets.push(tileset);
+                }
+                "layer" => {
+                    let name = node.attribute("name").unwrap_or_default().to_string();
+                    let tiles = parse_layer_data(&node)?;
+                    if tiles.len() != tile_count {
+                        return Err(MapError::Invalid(format!(
+                            "layer {:?} has {} tiles, expected {}",
+                            name,
+                            tiles.len(),
+                            tile_count
+                        )));
+                    }
+                    layers.push(Layer { name, tiles });
+                }
+                "objectgroup" if node.attribute("name") == Some(SPAWN_LAYER) => {
+                    spawn_objects.extend(node.children().filter(|n| n.has_tag_name("object")));
+                }
+                _ => {}
+            }
+        }
+
+        tilesets.sort_by_key(|tileset| tileset.first_gid);
+
+        let mut map = Map {
+            width,
+            height,
+            tile_width,
+            tile_height,
+            tilesets,
+            layers,
+            spawn_zones: Vec::new(),
+            solid: vec![false; tile_count],
+        };
+
+        for layer in map.layers.iter() {
+            for (index, gid) in layer.tiles.iter().enumerate() {
+                if let Some(tileset) = map.tileset_index(*gid).map(|i| &map.tilesets[i]) {
+                    if tileset.solid.contains(&(gid - tileset.first_gid)) {
+                        map.solid[index] = true;
+                    }
+                }
+            }
+        }
+
+        for object in spawn_objects {
+            let spawn_zone = map.spawn_zone(&object)?;
+            map.spawn_zones.push(spawn_zone);
+        }
+
+        Ok(map)
+    }
+
+    pub fn pixel_width(&self) -> f32 {
+        (self.width as u32 * self.tile_width) as f32
+    }
+
+    pub fn pixel_height(&self) -> f32 {
+        (self.height as u32 * self.tile_height) as f32
+    }
+
+    pub fn contains(&self, position: &Position) -> bool {
+        position.x < self.width && position.y < self.height
+    }
+
+    pub fn is_walkable(&self, position: &Position) -> bool {
+        self.index(position)
+            .map_or(false, |index| !self.solid[index])
+    }
+
+    // The neighbouring tile in `direction` if it is inside the map and walkable
+    pub fn step(&self, from: &Position, direction: Direction) -> Option<Position> {
+        from.step(direction).filter(|to| self.is_walkable(to))
+    }
+
+    // Position of the tile at `index` in a layer's tiles
+    pub fn position(&self, index: usize) -> Position {
+        let width = self.width as usize;
+        Position {
+            x: (index % width) as u16,
+            // Tiled rows go top-down but positions grow northwards from the bottom-left
+            y: self.height - 1 - (index / width) as u16,
+        }
+    }
+
+    fn index(&self, position: &Position) -> Option<usize> {
+        if !self.contains(position) {
+            return None;
+        }
+        let row = (self.height - 1 - position.y) as usize;
+        Some(row * self.width as usize + position.x as usize)
+    }
+
+    // The tileset a gid belongs to is the one with the highest firstgid not above it
+    pub fn tileset_index(&self, gid: u32) -> Option<usize> {
+        if gid == 0 {
+            // Empty cell
+            return None;
+        }
+
+        let index = self
+            .tilesets
+            .iter()
+            .rposition(|tileset| tileset.first_gid <= gid)?;
+        let tileset = &self.tilesets[index];
+
+        if gid - tileset.first_gid < tileset.tile_count {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    fn spawn_zone(&self, object: &roxmltree::Node) -> Result<SpawnZone, MapError> {
+        let x: f32 = attribute(object, "x")?;
+        let y: f32 = attribute(object, "y")?;
+        // Point objects have no size and cover the single tile they sit on
+        let width: f32 = attribute(object, "width").unwrap_or(0.0);
+        let height: f32 = attribute(object, "height").unwrap_or(0.0);
+
+        let tile_width = self.tile_width as f32;
+        let tile_height = self.tile_height as f32;
+        let left = (x / tile_width).floor().max(0.0) as u16;
+        let top = (y / tile_height).floor().max(0.0) as u16;
+        let right = ((x + width) / tile_width).ceil().max(left as f32 + 1.0) as u16;
+        let bottom = ((y + height) / tile_height).ceil().max(top as f32 + 1.0) as u16;
+        let right = right.min(self.width);
+        let bottom = bottom.min(self.height);
+
+        if left >= right || top >= bottom {
+            return Err(MapError::Invalid(format!(
+                "spawn object at ({}, {}) lies outside the map",
+                x, y
+            )));
+        }
+
+        Ok(SpawnZone {
+            origin: Position {
+                x: left,
+                y: self.height - bottom,
+            },
+            width: right - left,
+            height: bottom - top,
+        })
+    }
+}
+
+fn attribute<T: std::str::FromStr>(node: &roxmltree::Node, name: &str) -> Result<T, MapError> {
+    node.attribute(name)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| {
+            MapError::Invalid(format!(
+                "<{}> is missing a valid {:?} attribute",
+                node.tag_name().name(),
+                name
+            ))
+        })
+}
+
+fn parse_tileset(node: &roxmltree::Node, first_gid: u32, dir: &Path) -> Result<Tileset, MapError> {
+    let image = node
+        .children()
+        .find(|n| n.has_tag_name("image"))
+        .ok_or_else(|| MapError::Invalid("tileset has no <image>".into()))?;
+    let source: String = attribute(&image, "source")?;
+
+    let mut solid = HashSet::new();
+    for tile in node.children().filter(|n| n.has_tag_name("tile")) {
+        let is_solid = tile
+            .descendants()
+            .filter(|n| n.has_tag_name("property"))
+            .any(|property| {
+                property.attribute("name") == Some("solid")
+                    && property.attribute("value") == Some("true")
+            });
+        if is_solid {
+            solid.insert(attribute(&tile, "id")?);
+        }
+    }
+
+    Ok(Tileset {
+        first_gid,
+        tile_width: attribute(node, "tilewidth")?,
+        tile_height: attribute(node, "tileheight")?,
+        columns: attribute(node, "columns")?,
+        tile_count: attribute(node, "tilecount")?,
+        image: dir.join(source),
+        solid,
+    })
+}
+
+fn parse_layer_data(node: &roxmltree::Node) -> Result<Vec<u32>, MapError> {
+    let data = node
+        .children()
+        .find(|n| n.has_tag_name("data"))
+        .ok_or_else(|| MapError::Invalid("layer has no <data>".into()))?;
+
+    match data.attribute("encoding") {
+        Some("csv") => data
+            .text()
+            .unwrap_or_default()
+            .split(',')
+            .map(|gid| {
+                gid.trim()
+                    .parse::<u32>()
+                    .map(|gid| gid & !GID_FLAGS)
+                    .map_err(|_| MapError::Invalid(format!("invalid gid {:?}", gid.trim())))
+            })
+            .collect(),
+        encoding => Err(MapError::Invalid(format!(
+            "unsupported layer encoding {:?}; save the map with CSV layers",
+            encoding
+        ))),
+    }
+}
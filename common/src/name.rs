@@ -0,0 +1,27 @@
+// Display names are shown above sprites and in chat, so keep them short and printable
+pub const MAX_NAME_LENGTH: usize = 16;
+
+// Trims surrounding whitespace and checks what is left is a usable display name
+pub fn validate_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    let length = name.chars().count();
+
+    if length == 0 {
+        return Err("Please choose a name".to_string());
+    }
+    if length > MAX_NAME_LENGTH {
+        return Err(format!(
+            "Names can be at most {} characters long",
+            MAX_NAME_LENGTH
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_')
+    {
+        return Err("Names may only contain letters, digits, spaces, '-' and '_'".to_string());
+    }
+
+    // Collapse runs of spaces so look-alike names can't be made from whitespace
+    Ok(name.split_whitespace().collect::<Vec<_>>().join(" "))
+}
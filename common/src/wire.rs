@@ -0,0 +1,411 @@
+use bevy_spicy_networking::{ClientMessage, NetworkMessage, ServerMessage};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str};
+
+use crate::{
+    Chat, Credentials, Direction, EnteredView, Hello, Join, MoveInput, MoveUpdate, Ping, PlayerId,
+    PlayerLeft, Pong, Position, Rejected, Say, SessionToken, Snapshot, Speed, Welcome,
+};
+
+// The only message bevy_spicy_networking carries for us: one encoded WireMessage, starting with
+// its ID. Its names are kept short since they are repeated in every packet.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Packet(pub Vec<u8>);
+
+#[typetag::serde(name = "p")]
+impl NetworkMessage for Packet {}
+
+impl ServerMessage for Packet {
+    const NAME: &'static str = "p";
+}
+
+impl ClientMessage for Packet {
+    const NAME: &'static str = "p";
+}
+
+// Hello and Rejected as clients before protocol version 8 sent them: typetag JSON instead of a
+// Packet. The server still understands the old Hello, so those clients are told to update
+// rather than left waiting.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LegacyHello {
+    pub version: u32,
+}
+
+#[typetag::serde(name = "Hello")]
+impl NetworkMessage for LegacyHello {}
+
+impl ServerMessage for LegacyHello {
+    const NAME: &'static str = "woods:Hello";
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LegacyRejected(pub String);
+
+#[typetag::serde(name = "Rejected")]
+impl NetworkMessage for LegacyRejected {}
+
+impl ClientMessage for LegacyRejected {
+    const NAME: &'static str = "woods:Rejected";
+}
+
+// Packets are decoded into per-message events here, between bevy_spicy_networking receiving them
+// in PreUpdate and the game systems reading them in Update
+pub const DECODE_STAGE: &str = "decode_packets";
+
+#[derive(Debug, PartialEq)]
+pub enum WireError {
+    UnexpectedEnd,
+    VarintTooLong,
+    InvalidUtf8,
+    InvalidTag(&'static str, u8),
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireError::UnexpectedEnd => write!(f, "message ended early"),
+            WireError::VarintTooLong => write!(f, "integer is too large"),
+            WireError::InvalidUtf8 => write!(f, "string is not UTF-8"),
+            WireError::InvalidTag(name, tag) => write!(f, "invalid {} tag {}", name, tag),
+            WireError::TrailingBytes(count) => write!(f, "{} bytes left over", count),
+        }
+    }
+}
+
+// Something that can be written to and read back from the wire
+pub trait Wire: Sized {
+    fn encode(&self, writer: &mut Writer);
+    fn decode(reader: &mut Reader) -> Result<Self, WireError>;
+}
+
+// A top-level message. IDs are part of the protocol: never reuse or renumber one.
+pub trait WireMessage: Wire + Send + Sync + 'static {
+    const ID: u8;
+    // For logs
+    const NAME: &'static str;
+}
+
+pub fn encode<T: WireMessage>(message: &T) -> Packet {
+    let mut writer = Writer(vec![T::ID]);
+    message.encode(&mut writer);
+    Packet(writer.0)
+}
+
+pub fn message_id(packet: &Packet) -> Option<u8> {
+    packet.0.first().copied()
+}
+
+// Fails unless the packet holds exactly one T
+pub fn decode<T: WireMessage>(packet: &Packet) -> Result<T, WireError> {
+    let mut reader = Reader(&packet.0);
+    let id = reader.u8()?;
+    if id != T::ID {
+        return Err(WireError::InvalidTag("message", id));
+    }
+
+    let message = T::decode(&mut reader)?;
+    match reader.0.len() {
+        0 => Ok(message),
+        count => Err(WireError::TrailingBytes(count)),
+    }
+}
+
+pub struct Writer(Vec<u8>);
+
+impl Writer {
+    pub fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    // Little-endian base 128: seven bits per byte, high bit set on all but the last
+    pub fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.varint(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+}
+
+pub struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn u8(&mut self) -> Result<u8, WireError> {
+        let (first, rest) = self.0.split_first().ok_or(WireError::UnexpectedEnd)?;
+        self.0 = rest;
+        Ok(*first)
+    }
+
+    pub fn varint(&mut self) -> Result<u64, WireError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(WireError::VarintTooLong)
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], WireError> {
+        let length = self.varint()?;
+        if length > self.0.len() as u64 {
+            return Err(WireError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.0.split_at(length as usize);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    // Never more than there are bytes left, so a bogus length can't allocate much
+    fn capacity(&self, length: u64) -> usize {
+        length.min(self.0.len() as u64) as usize
+    }
+}
+
+impl Wire for u8 {
+    fn encode(&self, writer: &mut Writer) {
+        writer.u8(*self);
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, WireError> {
+        reader.u8()
+    }
+}
+
+macro_rules! varint {
+    ($($t:ty),*) => {
+        $(
+            impl Wire for $t {
+                fn encode(&self, writer: &mut Writer) {
+                    writer.varint(u64::from(*self));
+                }
+
+                fn decode(reader: &mut Reader) -> Result<Self, WireError> {
+                    let value = reader.varint()?;
+                    if value > u64::from(<$t>::MAX) {
+                        return Err(WireError::VarintTooLong);
+                    }
+                    Ok(value as $t)
+                }
+            }
+        )*
+    };
+}
+
+varint!(u16, u32, u64);
+
+impl Wire for String {
+    fn encode(&self, writer: &mut Writer) {
+        writer.bytes(self.as_bytes());
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, WireError> {
+        str::from_utf8(reader.bytes()?)
+            .map(String::from)
+            .map_err(|_| WireError::InvalidUtf8)
+    }
+}
+
+impl<T: Wire> Wire for Option<T> {
+    fn encode(&self, writer: &mut Writer) {
+        match self {
+            None => writer.u8(0),
+            Some(value) => {
+                writer.u8(1);
+                value.encode(writer);
+            }
+        }
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, WireError> {
+        match reader.u8()? {
+            0 => Ok(None),
+            1 => T::decode(reader).map(Some),
+            tag => Err(WireError::InvalidTag("option", tag)),
+        }
+    }
+}
+
+impl<T: Wire> Wire for Vec<T> {
+    fn encode(&self, writer: &mut Writer) {
+        writer.varint(self.len() as u64);
+        for item in self {
+            item.encode(writer);
+        }
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, WireError> {
+        let length = reader.varint()?;
+        let mut items = Vec::with_capacity(reader.capacity(length));
+        for _ in 0..length {
+            items.push(T::decode(reader)?);
+        }
+        Ok(items)
+    }
+}
+
+// Structs are their fields in order, with no tags or lengths
+macro_rules! wire_struct {
+    ($name:ident { $($field:ident),* }) => {
+        impl Wire for $name {
+            fn encode(&self, writer: &mut Writer) {
+                $(self.$field.encode(writer);)*
+            }
+
+            fn decode(reader: &mut Reader) -> Result<Self, WireError> {
+                Ok($name {
+                    $($field: Wire::decode(reader)?,)*
+                })
+            }
+        }
+    };
+    ($name:ident($inner:ty)) => {
+        impl Wire for $name {
+            fn encode(&self, writer: &mut Writer) {
+                self.0.encode(writer);
+            }
+
+            fn decode(reader: &mut Reader) -> Result<Self, WireError> {
+                <$inner>::decode(reader).map($name)
+            }
+        }
+    };
+}
+
+wire_struct!(PlayerId(u32));
+wire_struct!(Position { x, y });
+wire_struct!(Hello { version });
+wire_struct!(Join {
+    session,
+    credentials,
+    name
+});
+wire_struct!(MoveInput {
+    sequence,
+    direction,
+    position,
+    speed
+});
+wire_struct!(Say(String));
+wire_struct!(Rejected(String));
+wire_struct!(Welcome {
+    player_id,
+    position,
+    direction,
+    session,
+    name
+});
+wire_struct!(MoveUpdate {
+    player_id,
+    direction,
+    position,
+    distance,
+    speed,
+    sequence
+});
+wire_struct!(EnteredView {
+    player_id,
+    name,
+    direction,
+    position
+});
+wire_struct!(Snapshot {
+    tick,
+    entered,
+    moves,
+    left
+});
+wire_struct!(PlayerLeft(PlayerId));
+wire_struct!(Chat { player_id, text });
+wire_struct!(Ping { sequence, rtt });
+wire_struct!(Pong(u32));
+
+// Tokens are random, so a varint would only make them longer
+impl Wire for SessionToken {
+    fn encode(&self, writer: &mut Writer) {
+        for byte in self.0.to_le_bytes().iter() {
+            writer.u8(*byte);
+        }
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, WireError> {
+        let mut bytes = [0; 8];
+        for byte in bytes.iter_mut() {
+            *byte = reader.u8()?;
+        }
+        Ok(SessionToken(u64::from_le_bytes(bytes)))
+    }
+}
+
+impl Wire for Direction {
+    fn encode(&self, writer: &mut Writer) {
+        writer.u8(match self {
+            Direction::North => 0,
+            Direction::South => 1,
+            Direction::East => 2,
+            Direction::West => 3,
+        });
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, WireError> {
+        match reader.u8()? {
+            0 => Ok(Direction::North),
+            1 => Ok(Direction::South),
+            2 => Ok(Direction::East),
+            3 => Ok(Direction::West),
+            tag => Err(WireError::InvalidTag("direction", tag)),
+        }
+    }
+}
+
+impl Wire for Speed {
+    fn encode(&self, writer: &mut Writer) {
+        writer.u8(match self {
+            Speed::Walk => 0,
+            Speed::Run => 1,
+        });
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, WireError> {
+        match reader.u8()? {
+            0 => Ok(Speed::Walk),
+            1 => Ok(Speed::Run),
+            tag => Err(WireError::InvalidTag("speed", tag)),
+        }
+    }
+}
+
+impl Wire for Credentials {
+    fn encode(&self, writer: &mut Writer) {
+        match self {
+            Credentials::Password { username, password } => {
+                writer.u8(0);
+                username.encode(writer);
+                password.encode(writer);
+            }
+            Credentials::Token(token) => {
+                writer.u8(1);
+                token.encode(writer);
+            }
+        }
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, WireError> {
+        match reader.u8()? {
+            0 => Ok(Credentials::Password {
+                username: Wire::decode(reader)?,
+                password: Wire::decode(reader)?,
+            }),
+            1 => Ok(Credentials::Token(Wire::decode(reader)?)),
+            tag => Err(WireError::InvalidTag("credentials", tag)),
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use woods_common::{validate_name, Credentials};
+
+pub const INVALID_CREDENTIALS: &str = "Invalid username or password";
+
+const ACCOUNT_USAGE: &str = "Usage: woods-server [OPTIONS] account <COMMAND>
+
+Commands:
+    list                    List accounts
+    add <USERNAME>          Create an account; the password is read from stdin
+    password <USERNAME>     Change an account's password; read from stdin
+    disable <USERNAME>      Stop an account from logging in
+    enable <USERNAME>       Allow a disabled account to log in again
+    token <USERNAME>        Issue a login token and print it
+    revoke <USERNAME>       Revoke all of an account's login tokens
+    help                    Print this message";
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Account {
+    // As the user typed it; accounts are looked up ignoring case
+    name: String,
+    password_hash: Option<String>,
+    #[serde(default)]
+    disabled: bool,
+    #[serde(default)]
+    token_hashes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct AccountsFile {
+    #[serde(default)]
+    accounts: BTreeMap<String, Account>,
+}
+
+// Accounts are kept in a flat file so they can be managed with the account command while the
+// server is running
+pub struct Accounts {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    accounts: BTreeMap<String, Account>,
+    // Checked against when there is nothing real to check, so every login takes as long
+    dummy_hash: String,
+}
+
+// Credentials that still need their secret checked. Hashing is slow on purpose, so this is
+// done off the main thread.
+pub struct Login {
+    pub name: String,
+    secret: String,
+    hashes: Vec<String>,
+    disabled: bool,
+    dummy_hash: String,
+}
+
+impl Login {
+    // Unknown and disabled accounts only show as such once the secret checks out, so neither
+    // the answer nor how long it takes gives away which accounts exist
+    pub fn verify(&self) -> Result<(), String> {
+        let verified = if self.hashes.is_empty() {
+            verify(&self.secret, &self.dummy_hash);
+            false
+        } else {
+            self.hashes.iter().any(|hash| verify(&self.secret, hash))
+        };
+
+        if !verified {
+            return Err(INVALID_CREDENTIALS.to_string());
+        }
+        if self.disabled {
+            return Err("This account has been disabled".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Accounts {
+    // A missing file means there are no accounts yet
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut accounts = Self {
+            path: path.to_path_buf(),
+            modified: None,
+            accounts: BTreeMap::new(),
+            dummy_hash: hash("")?,
+        };
+        accounts.reload()?;
+        Ok(accounts)
+    }
+
+    // Picks up changes made by the account command since the file was last read
+    pub fn refresh(&mut self) {
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        if modified != self.modified {
+            if let Err(err) = self.reload() {
+                log::error!("{}", err);
+            }
+        }
+    }
+
+    pub fn login(&self, credentials: &Credentials) -> Login {
+        let secret = match credentials {
+            Credentials::Password { password, .. } => password.clone(),
+            Credentials::Token(token) => {
+                let secret = token.splitn(2, ':').nth(1).unwrap_or_default();
+                secret.to_string()
+            }
+        };
+
+        let account = match self.accounts.get(&key(credentials.username())) {
+            Some(account) => account,
+            None => {
+                return Login {
+                    name: credentials.username().to_string(),
+                    secret,
+                    hashes: Vec::new(),
+                    disabled: false,
+                    dummy_hash: self.dummy_hash.clone(),
+                }
+            }
+        };
+
+        let hashes = match credentials {
+            Credentials::Password { .. } => account.password_hash.iter().cloned().collect(),
+            Credentials::Token(_) => account.token_hashes.clone(),
+        };
+
+        Login {
+            name: account.name.clone(),
+            secret,
+            hashes,
+            disabled: account.disabled,
+            dummy_hash: self.dummy_hash.clone(),
+        }
+    }
+
+    fn reload(&mut self) -> Result<(), String> {
+        self.modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        self.accounts = match fs::read_to_string(&self.path) {
+            Ok(text) => {
+                let file: AccountsFile = toml::from_str(&text)
+                    .map_err(|err| format!("Invalid accounts file {:?}: {}", self.path, err))?;
+                file.accounts
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => {
+                return Err(format!(
+                    "Could not read accounts file {:?}: {}",
+                    self.path, err
+                ))
+            }
+        };
+        Ok(())
+    }
+
+    pub fn add(&mut self, name: &str, password: &str) -> Result<String, String> {
+        let name = validate_name(name)?;
+        if self.accounts.contains_key(&key(&name)) {
+            return Err(format!("There is already an account named {:?}", name));
+        }
+        self.accounts.insert(
+            key(&name),
+            Account {
+                name: name.clone(),
+                password_hash: Some(hash(password)?),
+                disabled: false,
+                token_hashes: Vec::new(),
+            },
+        );
+        Ok(name)
+    }
+
+    // Saved straight away, so the account command and a restart both see it
+    pub fn disable(&mut self, name: &str) -> Result<(), String> {
+        self.refresh();
+        self.get_mut(name)?.disabled = true;
+        self.save()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let file = AccountsFile {
+            accounts: self.accounts.clone(),
+        };
+        let text = toml::to_string(&file).map_err(|err| err.to_string())?;
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, text)
+            .and_then(|_| fs::rename(&temporary, &self.path))
+            .map_err(|err| format!("Could not save accounts to {:?}: {}", self.path, err))
+    }
+
+    fn get_mut(&mut self, name: &str) -> Result<&mut Account, String> {
+        self.accounts
+            .get_mut(&key(name))
+            .ok_or_else(|| format!("No account named {:?}", name))
+    }
+}
+
+// Runs `woods-server account ...`
+pub fn run_command(path: &Path, args: &[String]) -> Result<(), String> {
+    let mut accounts = Accounts::load(path)?;
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["list"] => {
+            for account in accounts.accounts.values() {
+                println!(
+                    "{}{} ({} tokens)",
+                    account.name,
+                    if account.disabled { " [disabled]" } else { "" },
+                    account.token_hashes.len()
+                );
+            }
+            return Ok(());
+        }
+        ["add", name] => {
+            let name = accounts.add(name, &read_password()?)?;
+            println!("Created account {}", name);
+        }
+        ["password", name] => {
+            let password_hash = Some(hash(&read_password()?)?);
+            accounts.get_mut(name)?.password_hash = password_hash;
+            println!("Changed the password for {}", name);
+        }
+        ["disable", name] => {
+            accounts.get_mut(name)?.disabled = true;
+            println!("Disabled {}", name);
+        }
+        ["enable", name] => {
+            accounts.get_mut(name)?.disabled = false;
+            println!("Enabled {}", name);
+        }
+        ["token", name] => {
+            let account = accounts.get_mut(name)?;
+            let secret = format!("{:032x}", rand::random::<u128>());
+            account.token_hashes.push(hash(&secret)?);
+            // Only the hash is kept, so this is the one chance to see the token
+            println!("{}:{}", account.name, secret);
+        }
+        ["revoke", name] => {
+            accounts.get_mut(name)?.token_hashes.clear();
+            println!("Revoked all tokens for {}", name);
+        }
+        ["help"] => {
+            println!("{}", ACCOUNT_USAGE);
+            return Ok(());
+        }
+        _ => return Err(ACCOUNT_USAGE.to_string()),
+    }
+
+    accounts.save()
+}
+
+// Read as a plain line so accounts can be scripted; note that a terminal will echo it
+fn read_password() -> Result<String, String> {
+    eprint!("Password: ");
+    io::stderr().flush().ok();
+
+    let mut password = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut password)
+        .map_err(|err| format!("Could not read password: {}", err))?;
+    let password = password.trim_end_matches(&['\r', '\n'][..]).to_string();
+
+    if password.is_empty() {
+        return Err("The password can't be empty".to_string());
+    }
+    Ok(password)
+}
+
+fn key(name: &str) -> String {
+    name.to_lowercase()
+}
+
+fn hash(secret: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(secret.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| format!("Could not hash secret: {}", err))
+}
+
+fn verify(secret: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(secret.as_bytes(), &hash)
+            .is_ok(),
+        Err(err) => {
+            log::warn!("Ignoring malformed hash in accounts file: {}", err);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "correct horse battery staple";
+
+    fn login(accounts: &Accounts, username: &str, password: &str) -> Result<(), String> {
+        accounts
+            .login(&Credentials::Password {
+                username: username.to_string(),
+                password: password.to_string(),
+            })
+            .verify()
+    }
+
+    #[test]
+    fn only_says_an_account_is_disabled_to_its_owner() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut accounts = Accounts::load(&dir.path().join("accounts.toml")).unwrap();
+        accounts.add("alice", PASSWORD).unwrap();
+        accounts.disable("alice").unwrap();
+
+        let invalid = Err(INVALID_CREDENTIALS.to_string());
+        assert_eq!(login(&accounts, "mallory", PASSWORD), invalid);
+        assert_eq!(login(&accounts, "alice", "guess"), invalid);
+        assert_eq!(
+            login(&accounts, "alice", PASSWORD),
+            Err("This account has been disabled".to_string())
+        );
+    }
+
+    #[test]
+    fn asking_for_help_succeeds() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("accounts.toml");
+        assert_eq!(run_command(&path, &["help".to_string()]), Ok(()));
+        assert!(run_command(&path, &["fly".to_string()]).is_err());
+        // Nothing was written
+        assert!(!path.exists());
+    }
+}
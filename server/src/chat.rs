@@ -0,0 +1,108 @@
+use bevy::prelude::*;
+use bevy_spicy_networking::NetworkServer;
+use std::collections::VecDeque;
+
+use woods_common::{Chat, PlayerId, Say, MAX_CHAT_LENGTH};
+
+use crate::{
+    network::Players,
+    wire::{self, AppWireMessage, Received},
+};
+
+// Each player may send at most this many messages in any RATE_WINDOW seconds
+const RATE_LIMIT: usize = 5;
+const RATE_WINDOW: f64 = 10.0;
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(handle_chat.system())
+            .listen_for_wire_message::<Say>();
+    }
+}
+
+// When the player's recent messages were sent, in seconds since startup. Inserted when the
+// player spawns, so the limit holds from the very first message.
+#[derive(Default)]
+pub struct ChatHistory(VecDeque<f64>);
+
+impl ChatHistory {
+    // Records a message sent at `now` unless the player is over the rate limit
+    fn allow(&mut self, now: f64) -> bool {
+        while matches!(self.0.front(), Some(sent) if now - sent > RATE_WINDOW) {
+            self.0.pop_front();
+        }
+        if self.0.len() >= RATE_LIMIT {
+            return false;
+        }
+        self.0.push_back(now);
+        true
+    }
+}
+
+fn handle_chat(
+    time: Res<Time>,
+    net: Res<NetworkServer>,
+    players: Res<Players>,
+    mut says: EventReader<Received<Say>>,
+    mut query: Query<(&PlayerId, &mut ChatHistory)>,
+) {
+    let now = time.seconds_since_startup();
+
+    for say in says.iter() {
+        let connection_id = *say.source();
+        let player = match players.0.get(&connection_id) {
+            Some(player) => *player,
+            None => {
+                log::warn!("Ignoring chat from {:?} before joining", connection_id);
+                continue;
+            }
+        };
+        let (player_id, mut history) = match query.get_mut(player) {
+            Ok(result) => result,
+            Err(_) => {
+                log::warn!("Chat from player without PlayerId {:?}", connection_id);
+                continue;
+            }
+        };
+
+        let text = clean(&say.0);
+        if text.is_empty() {
+            continue;
+        }
+
+        if !history.allow(now) {
+            log::debug!("{:?} is chatting too fast", player_id);
+            let notice = Chat {
+                player_id: None,
+                text: "You are sending messages too quickly; slow down.".to_string(),
+            };
+            wire::send(&net, connection_id, &notice);
+            continue;
+        }
+
+        broadcast(&net, &players, Some(*player_id), text);
+    }
+}
+
+// Drops control characters and surrounding whitespace, and enforces the length limit
+fn clean(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .trim()
+        .chars()
+        .take(MAX_CHAT_LENGTH)
+        .collect()
+}
+
+pub fn broadcast(
+    net: &NetworkServer,
+    players: &Players,
+    player_id: Option<PlayerId>,
+    text: String,
+) {
+    log::info!("[CHAT] {:?}: {}", player_id, text);
+    players.broadcast(net, &Chat { player_id, text });
+}
@@ -0,0 +1,223 @@
+use serde::Deserialize;
+use std::{
+    env, fmt, fs,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use woods_common::SERVER_PORT;
+
+use crate::spawn::SpawnPolicy;
+
+// Read from the working directory when neither --config nor WOODS_CONFIG is given
+const DEFAULT_CONFIG_FILE: &str = "woods-server.toml";
+
+// Where player state is kept between restarts, relative to the working directory
+const DEFAULT_STORE_PATH: &str = "woods-players.toml";
+
+// Login accounts, managed with `woods-server account`
+const DEFAULT_ACCOUNTS_PATH: &str = "woods-accounts.toml";
+
+// Players further than this many tiles away in either direction are out of view. Wide enough
+// to cover the client's screen from its centre.
+const DEFAULT_VIEW_RADIUS: u16 = 16;
+
+// The server reads the same map the client renders, found in its assets directory
+const DEFAULT_MAP_FILE: &str = "field.tmx";
+
+const USAGE: &str = "Usage: woods-server [OPTIONS] [account <COMMAND>]
+
+Options (each can also be set with the environment variable or config file key shown):
+    --config <PATH>         Config file [WOODS_CONFIG] (default: ./woods-server.toml if present)
+    --address <IP>          Address to listen on [WOODS_ADDRESS, address] (default: 127.0.0.1)
+    --port <PORT>           Port to listen on [WOODS_PORT, port] (default: 14192)
+    --map <PATH>            Tiled map to serve [WOODS_MAP, map] (default: assets/field.tmx next to the executable)
+    --spawn-policy <POLICY> random or round-robin [WOODS_SPAWN_POLICY, spawn_policy]
+    --store <PATH>          Saved player state [WOODS_STORE, store] (default: ./woods-players.toml)
+    --accounts <PATH>       Login accounts [WOODS_ACCOUNTS, accounts] (default: ./woods-accounts.toml)
+    --view-radius <TILES>   How far players can see others [WOODS_VIEW_RADIUS, view_radius] (default: 16)
+    --console <ADDRESS>     Also take admin commands over TCP on this loopback address [WOODS_CONSOLE, console]
+    -h, --help              Print this message
+
+Run `woods-server account help` to manage accounts instead of starting the server.";
+
+pub struct Config {
+    pub listen: SocketAddr,
+    pub map: PathBuf,
+    pub spawn_policy: SpawnPolicy,
+    pub store: PathBuf,
+    pub accounts: PathBuf,
+    pub view_radius: u16,
+    // Where to accept admin console connections, besides stdin
+    pub console: Option<SocketAddr>,
+    // Arguments after `account`, when running an account command instead of the server
+    pub account_command: Option<Vec<String>>,
+}
+
+// Settings from any one source; later sources override earlier ones
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Settings {
+    address: Option<IpAddr>,
+    port: Option<u16>,
+    map: Option<PathBuf>,
+    spawn_policy: Option<String>,
+    store: Option<PathBuf>,
+    accounts: Option<PathBuf>,
+    view_radius: Option<u16>,
+    console: Option<SocketAddr>,
+}
+
+impl Settings {
+    fn or(self, fallback: Settings) -> Settings {
+        Settings {
+            address: self.address.or(fallback.address),
+            port: self.port.or(fallback.port),
+            map: self.map.or(fallback.map),
+            spawn_policy: self.spawn_policy.or(fallback.spawn_policy),
+            store: self.store.or(fallback.store),
+            accounts: self.accounts.or(fallback.accounts),
+            view_radius: self.view_radius.or(fallback.view_radius),
+            console: self.console.or(fallback.console),
+        }
+    }
+}
+
+impl Config {
+    // Command line arguments take precedence over environment variables, which take precedence
+    // over the config file
+    pub fn load() -> Result<Self, String> {
+        let mut config_path = env::var_os("WOODS_CONFIG").map(PathBuf::from);
+        let mut account_command = None;
+        let args = parse_args(env::args().skip(1), &mut config_path, &mut account_command)?;
+
+        let file = match config_path {
+            Some(path) => read_config_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                read_config_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Settings::default(),
+        };
+
+        let env = Settings {
+            address: env_var("WOODS_ADDRESS")?,
+            port: env_var("WOODS_PORT")?,
+            map: env::var_os("WOODS_MAP").map(PathBuf::from),
+            spawn_policy: env::var("WOODS_SPAWN_POLICY").ok(),
+            store: env::var_os("WOODS_STORE").map(PathBuf::from),
+            accounts: env::var_os("WOODS_ACCOUNTS").map(PathBuf::from),
+            view_radius: env_var("WOODS_VIEW_RADIUS")?,
+            console: env_var("WOODS_CONSOLE")?,
+        };
+
+        let settings = args.or(env).or(file);
+
+        // Anyone who can reach the console can run it, so it stays on this machine
+        if let Some(console) = settings.console {
+            if !console.ip().is_loopback() {
+                return Err(format!(
+                    "The console can only listen on a loopback address, not {}",
+                    console
+                ));
+            }
+        }
+
+        Ok(Config {
+            listen: SocketAddr::new(
+                settings.address.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+                settings.port.unwrap_or(SERVER_PORT),
+            ),
+            map: settings.map.unwrap_or_else(default_map_path),
+            spawn_policy: match settings.spawn_policy {
+                Some(policy) => policy.parse()?,
+                None => Default::default(),
+            },
+            store: settings.store.unwrap_or_else(|| DEFAULT_STORE_PATH.into()),
+            accounts: settings
+                .accounts
+                .unwrap_or_else(|| DEFAULT_ACCOUNTS_PATH.into()),
+            view_radius: settings.view_radius.unwrap_or(DEFAULT_VIEW_RADIUS),
+            console: settings.console,
+            account_command,
+        })
+    }
+}
+
+// Looked up when the server starts, the way the client finds its assets: the client's own
+// directory under cargo, and otherwise the directory the executable is in
+pub fn default_map_path() -> PathBuf {
+    env::var("CARGO_MANIFEST_DIR")
+        .map(|dir| Path::new(&dir).join("../client"))
+        .unwrap_or_else(|_| {
+            env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(Path::to_path_buf))
+                .unwrap_or_default()
+        })
+        .join("assets")
+        .join(DEFAULT_MAP_FILE)
+}
+
+fn parse_args(
+    mut args: impl Iterator<Item = String>,
+    config_path: &mut Option<PathBuf>,
+    account_command: &mut Option<Vec<String>>,
+) -> Result<Settings, String> {
+    let mut settings = Settings::default();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{} requires a value\n\n{}", arg, USAGE))
+        };
+
+        match arg.as_str() {
+            "--config" => *config_path = Some(value()?.into()),
+            "--address" => settings.address = Some(parse(&arg, &value()?)?),
+            "--port" => settings.port = Some(parse(&arg, &value()?)?),
+            "--map" => settings.map = Some(value()?.into()),
+            "--spawn-policy" => settings.spawn_policy = Some(value()?),
+            "--store" => settings.store = Some(value()?.into()),
+            "--accounts" => settings.accounts = Some(value()?.into()),
+            "--view-radius" => settings.view_radius = Some(parse(&arg, &value()?)?),
+            "--console" => settings.console = Some(parse(&arg, &value()?)?),
+            "account" => {
+                *account_command = Some(args.collect());
+                break;
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => return Err(format!("Unknown argument {:?}\n\n{}", arg, USAGE)),
+        }
+    }
+
+    Ok(settings)
+}
+
+fn read_config_file(path: &Path) -> Result<Settings, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Could not read config file {:?}: {}", path, err))?;
+    toml::from_str(&text).map_err(|err| format!("Invalid config file {:?}: {}", path, err))
+}
+
+fn env_var<T: FromStr>(name: &str) -> Result<Option<T>, String>
+where
+    T::Err: fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) => parse(name, &value).map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|err| format!("Invalid value {:?} for {}: {}", value, name, err))
+}
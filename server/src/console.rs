@@ -0,0 +1,517 @@
+use bevy::{app::AppExit, prelude::*};
+use bevy_spicy_networking::{ConnectionId, NetworkServer};
+use crossbeam_channel::{Receiver, Sender};
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::TcpStream,
+    thread,
+};
+
+use woods_common::{Direction, Map, MoveUpdate, PlayerId, Position, Speed};
+
+use crate::{
+    accounts::Accounts,
+    chat,
+    config::Config,
+    handshake::Handshakes,
+    interest::Interest,
+    network::{AccountName, DisplayName, InputSequence, Players, TickMoves},
+    occupancy::Occupancy,
+    persistence::{self, PlayerStore},
+    session::Lingering,
+};
+
+const HELP: &str = "Commands:
+    list                         List everyone in the world
+    kick <PLAYER>                Disconnect a player and take it out of the world
+    ban <PLAYER>                 Disable a player's account and kick it
+    teleport <PLAYER> <X> <Y>    Move a player to a tile
+    broadcast <TEXT>             Send a message to everyone
+    shutdown                     Save everyone and stop the server
+    help                         Print this message
+
+PLAYER is a name, or #ID for a player ID.";
+
+// Admin commands typed on stdin or, with --console, over a local TCP connection. Each command is
+// carried out by its own system, so it sees the world the same way the rest of the server does.
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        app.insert_resource(Console { sender, receiver })
+            .add_event::<ConsoleCommand>()
+            .add_startup_system(setup_console_listener.system())
+            .add_system(read_console.system().label("read_console"))
+            .add_system(list_players.system().after("read_console"))
+            .add_system(kick_players.system().after("read_console"))
+            .add_system(ban_players.system().after("read_console"))
+            .add_system(teleport_players.system().after("read_console"))
+            .add_system(broadcast_messages.system().after("read_console"))
+            .add_system(shut_down.system().after("read_console"));
+    }
+}
+
+// A line typed at the console, and where to send the answer
+pub struct ConsoleLine {
+    pub text: String,
+    pub reply: Sender<String>,
+}
+
+pub struct Console {
+    sender: Sender<ConsoleLine>,
+    receiver: Receiver<ConsoleLine>,
+}
+
+impl Console {
+    pub fn sender(&self) -> Sender<ConsoleLine> {
+        self.sender.clone()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Name(String),
+    Id(PlayerId),
+}
+
+impl Target {
+    fn matches(&self, player_id: &PlayerId, DisplayName(name): &DisplayName) -> bool {
+        match self {
+            // Ignoring case the same way as account names and the uniqueness of display names
+            Target::Name(target) => target.to_lowercase() == name.to_lowercase(),
+            Target::Id(target) => target == player_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    List,
+    Kick(Target),
+    Ban(Target),
+    Teleport(Target, Position),
+    Broadcast(String),
+    Shutdown,
+}
+
+pub struct ConsoleCommand {
+    pub command: Command,
+    reply: Sender<String>,
+}
+
+impl ConsoleCommand {
+    fn reply(&self, text: impl Into<String>) {
+        // Nothing to do if whoever typed it has gone
+        self.reply.send(text.into()).ok();
+    }
+}
+
+// Help comes back as the error, along with anything that doesn't parse
+pub fn parse(line: &str) -> Result<Command, String> {
+    let mut words = line.trim().splitn(2, char::is_whitespace);
+    let command = words.next().unwrap_or_default();
+    let rest = words.next().unwrap_or_default().trim();
+    let args: Vec<&str> = rest.split_whitespace().collect();
+
+    match (command, args.as_slice()) {
+        ("list", []) => Ok(Command::List),
+        ("kick", [target]) => Ok(Command::Kick(parse_target(target)?)),
+        ("ban", [target]) => Ok(Command::Ban(parse_target(target)?)),
+        ("teleport", [target, x, y]) => Ok(Command::Teleport(
+            parse_target(target)?,
+            Position {
+                x: parse_coordinate(x)?,
+                y: parse_coordinate(y)?,
+            },
+        )),
+        ("broadcast", [_, ..]) => Ok(Command::Broadcast(rest.to_string())),
+        ("shutdown", []) => Ok(Command::Shutdown),
+        ("help", _) => Err(HELP.to_string()),
+        _ => Err(format!("Unknown command {:?}\n\n{}", line.trim(), HELP)),
+    }
+}
+
+fn parse_target(word: &str) -> Result<Target, String> {
+    match word.strip_prefix('#') {
+        Some(id) => id
+            .parse()
+            .map(|id| Target::Id(PlayerId(id)))
+            .map_err(|_| format!("Invalid player ID {:?}", word)),
+        None => Ok(Target::Name(word.to_string())),
+    }
+}
+
+fn parse_coordinate(word: &str) -> Result<u16, String> {
+    word.parse()
+        .map_err(|_| format!("Invalid coordinate {:?}", word))
+}
+
+// Sends stdin to the console and prints what comes back. Only the binary calls this, so tests
+// never block on stdin.
+pub fn read_stdin(console: Sender<ConsoleLine>) {
+    let (reply, replies) = crossbeam_channel::unbounded::<String>();
+    thread::spawn(move || {
+        for text in replies {
+            println!("{}", text);
+        }
+    });
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let text = match line {
+                Ok(text) => text,
+                Err(err) => {
+                    log::error!("Could not read the console: {}", err);
+                    break;
+                }
+            };
+            if console
+                .send(ConsoleLine {
+                    text,
+                    reply: reply.clone(),
+                })
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+}
+
+fn setup_console_listener(config: Res<Config>, console: Res<Console>) {
+    let address = match config.console {
+        Some(address) => address,
+        None => return,
+    };
+
+    let listener = match std::net::TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(err) => {
+            log::error!("Could not open the console on {}: {}", address, err);
+            return;
+        }
+    };
+    log::info!("Console listening on {}", address);
+
+    let sender = console.sender();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => serve_console(stream, sender.clone()),
+                Err(err) => log::warn!("Console connection failed: {}", err),
+            }
+        }
+    });
+}
+
+// One thread reads commands off the connection and another writes the answers back
+fn serve_console(stream: TcpStream, console: Sender<ConsoleLine>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(err) => {
+            log::warn!("Console connection failed: {}", err);
+            return;
+        }
+    };
+    let peer = stream.peer_addr().ok();
+    log::info!("Console connection from {:?}", peer);
+
+    let (reply, replies) = crossbeam_channel::unbounded::<String>();
+    thread::spawn(move || {
+        for text in replies {
+            if writeln!(writer, "{}", text).is_err() {
+                break;
+            }
+        }
+    });
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let text = match line {
+                Ok(text) => text,
+                Err(_) => break,
+            };
+            if console
+                .send(ConsoleLine {
+                    text,
+                    reply: reply.clone(),
+                })
+                .is_err()
+            {
+                break;
+            }
+        }
+        log::info!("Console connection from {:?} closed", peer);
+    });
+}
+
+fn read_console(console: Res<Console>, mut console_commands: EventWriter<ConsoleCommand>) {
+    for ConsoleLine { text, reply } in console.receiver.try_iter() {
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        match parse(&text) {
+            Ok(command) => {
+                log::info!("[CONSOLE] {}", text.trim());
+                console_commands.send(ConsoleCommand { command, reply });
+            }
+            Err(err) => {
+                reply.send(err).ok();
+            }
+        }
+    }
+}
+
+fn list_players(
+    mut console_commands: EventReader<ConsoleCommand>,
+    query: Query<(&PlayerId, &DisplayName, &Position, Option<&ConnectionId>)>,
+) {
+    for console_command in console_commands.iter() {
+        if console_command.command != Command::List {
+            continue;
+        }
+
+        let mut players: Vec<_> = query.iter().collect();
+        players.sort_by_key(|(player_id, ..)| player_id.0);
+
+        let mut lines = vec![format!("{} in the world", players.len())];
+        for (player_id, DisplayName(name), position, connection_id) in players {
+            // Players without a connection are lingering, waiting to resume their session
+            let connection = match connection_id {
+                Some(connection_id) => format!("{:?}", connection_id),
+                None => "lingering".to_string(),
+            };
+            lines.push(format!(
+                "#{} {} at ({}, {}), {}",
+                player_id.0, name, position.x, position.y, connection
+            ));
+        }
+        console_command.reply(lines.join("\n"));
+    }
+}
+
+// Closes the player's connection, if it has one, and leaves the player lingering with no time
+// left, so it is saved and removed like any other instead of waiting to be resumed
+fn kick(
+    commands: &mut Commands,
+    net: &NetworkServer,
+    handshakes: &mut Handshakes,
+    players: &mut Players,
+    player: Entity,
+    connection_id: Option<&ConnectionId>,
+    reason: &str,
+) {
+    if let Some(connection_id) = connection_id {
+        handshakes.reject(net, *connection_id, reason.to_string());
+        players.0.remove(connection_id);
+    }
+    commands
+        .entity(player)
+        .remove::<ConnectionId>()
+        .remove::<Interest>()
+        .insert(Lingering::expired());
+}
+
+fn kick_players(
+    mut commands: Commands,
+    net: Res<NetworkServer>,
+    mut handshakes: ResMut<Handshakes>,
+    mut players: ResMut<Players>,
+    mut console_commands: EventReader<ConsoleCommand>,
+    query: Query<(Entity, &PlayerId, &DisplayName, Option<&ConnectionId>)>,
+) {
+    for console_command in console_commands.iter() {
+        let target = match &console_command.command {
+            Command::Kick(target) => target,
+            _ => continue,
+        };
+
+        match query
+            .iter()
+            .find(|(_, player_id, name, _)| target.matches(player_id, name))
+        {
+            Some((player, _, DisplayName(name), connection_id)) => {
+                kick(
+                    &mut commands,
+                    &net,
+                    &mut handshakes,
+                    &mut players,
+                    player,
+                    connection_id,
+                    "Kicked by an admin",
+                );
+                console_command.reply(format!("Kicked {}", name));
+            }
+            None => console_command.reply(format!("No player {:?}", target)),
+        }
+    }
+}
+
+fn ban_players(
+    mut commands: Commands,
+    net: Res<NetworkServer>,
+    mut accounts: ResMut<Accounts>,
+    mut handshakes: ResMut<Handshakes>,
+    mut players: ResMut<Players>,
+    mut console_commands: EventReader<ConsoleCommand>,
+    query: Query<(
+        Entity,
+        &PlayerId,
+        &DisplayName,
+        &AccountName,
+        Option<&ConnectionId>,
+    )>,
+) {
+    for console_command in console_commands.iter() {
+        let target = match &console_command.command {
+            Command::Ban(target) => target,
+            _ => continue,
+        };
+
+        let (player, DisplayName(name), AccountName(account), connection_id) = match query
+            .iter()
+            .find(|(_, player_id, name, ..)| target.matches(player_id, name))
+        {
+            Some((player, _, name, account, connection_id)) => {
+                (player, name, account, connection_id)
+            }
+            None => {
+                console_command.reply(format!("No player {:?}", target));
+                continue;
+            }
+        };
+
+        if let Err(err) = accounts.disable(account) {
+            console_command.reply(err);
+            continue;
+        }
+        kick(
+            &mut commands,
+            &net,
+            &mut handshakes,
+            &mut players,
+            player,
+            connection_id,
+            "Banned by an admin",
+        );
+        console_command.reply(format!("Banned {}", name));
+    }
+}
+
+fn teleport_players(
+    map: Res<Map>,
+    mut occupancy: ResMut<Occupancy>,
+    mut tick_moves: ResMut<TickMoves>,
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut query: Query<(
+        Entity,
+        &PlayerId,
+        &DisplayName,
+        &mut Position,
+        &Direction,
+        &InputSequence,
+    )>,
+) {
+    for console_command in console_commands.iter() {
+        let (target, destination) = match &console_command.command {
+            Command::Teleport(target, destination) => (target, *destination),
+            _ => continue,
+        };
+
+        if !map.is_walkable(&destination) {
+            console_command.reply(format!(
+                "({}, {}) isn't walkable",
+                destination.x, destination.y
+            ));
+            continue;
+        }
+
+        let (player, player_id, DisplayName(name), mut position, direction, input_sequence) =
+            match query
+                .iter_mut()
+                .find(|(_, player_id, name, ..)| target.matches(player_id, name))
+            {
+                Some(found) => found,
+                None => {
+                    console_command.reply(format!("No player {:?}", target));
+                    continue;
+                }
+            };
+
+        if !occupancy.move_player(player, &position, destination) {
+            console_command.reply(format!("({}, {}) is taken", destination.x, destination.y));
+            continue;
+        }
+        *position = destination;
+
+        // Goes out with the next snapshot. A move of no distance places the player without
+        // walking, and the mover's client takes it as a correction.
+        tick_moves.0.push((
+            player,
+            MoveUpdate {
+                player_id: *player_id,
+                direction: *direction,
+                position: destination,
+                distance: 0,
+                speed: Speed::Walk,
+                sequence: input_sequence.0,
+            },
+        ));
+        console_command.reply(format!(
+            "Teleported {} to ({}, {})",
+            name, destination.x, destination.y
+        ));
+    }
+}
+
+fn broadcast_messages(
+    net: Res<NetworkServer>,
+    players: Res<Players>,
+    mut console_commands: EventReader<ConsoleCommand>,
+) {
+    for console_command in console_commands.iter() {
+        if let Command::Broadcast(text) = &console_command.command {
+            chat::broadcast(&net, &players, None, text.clone());
+            console_command.reply(format!("Sent to {} players", players.0.len()));
+        }
+    }
+}
+
+// Everyone is saved and told why they are being disconnected. The server exits once the
+// connections are closed, so the messages have a chance to get out first.
+fn shut_down(
+    net: Res<NetworkServer>,
+    players: Res<Players>,
+    mut handshakes: ResMut<Handshakes>,
+    mut store: ResMut<PlayerStore>,
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut shutting_down: Local<bool>,
+    mut app_exit: EventWriter<AppExit>,
+    query: Query<(&AccountName, &Position, &Direction)>,
+) {
+    if *shutting_down {
+        if !handshakes.closing() {
+            app_exit.send(AppExit);
+        }
+        return;
+    }
+
+    for console_command in console_commands.iter() {
+        if console_command.command != Command::Shutdown {
+            continue;
+        }
+
+        persistence::save_players(&mut store, query.iter());
+        let reason = "The server is shutting down";
+        chat::broadcast(&net, &players, None, reason.to_string());
+        for connection_id in players.0.keys().copied().chain(handshakes.pending()) {
+            handshakes.reject(&net, connection_id, reason.to_string());
+        }
+        console_command.reply(format!(
+            "Saved {} players; shutting down",
+            query.iter().count()
+        ));
+        *shutting_down = true;
+    }
+}
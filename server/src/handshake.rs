@@ -0,0 +1,291 @@
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use bevy_spicy_networking::{
+    AppNetworkServerMessage, ConnectionId, NetworkData, NetworkServer, ServerNetworkEvent,
+};
+use futures_lite::future;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use woods_common::{
+    validate_name,
+    wire::{LegacyHello, LegacyRejected},
+    Hello, Join, Rejected, SessionToken, PROTOCOL_VERSION,
+};
+
+use crate::{
+    accounts::Accounts,
+    wire::{self, AppWireMessage, Received},
+};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Gives Rejected a chance to reach the client before the connection is closed
+pub const CLOSE_DELAY: Duration = Duration::from_secs(1);
+
+pub struct HandshakePlugin;
+
+impl Plugin for HandshakePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<Handshaken>()
+            .init_resource::<Handshakes>()
+            .add_system(
+                track_connections
+                    .system()
+                    .label("handshake")
+                    .label("connections"),
+            )
+            .add_system(
+                handle_hellos
+                    .system()
+                    .label("handshake")
+                    .label("hello")
+                    .after("connections"),
+            )
+            .add_system(
+                handle_joins
+                    .system()
+                    .label("handshake")
+                    .label("join")
+                    .after("hello"),
+            )
+            .add_system(finish_logins.system().label("handshake").after("join"))
+            .add_system(reject_legacy_hellos.system().after("connections"))
+            .add_system(expire_handshakes.system())
+            .listen_for_server_message::<LegacyHello>()
+            .listen_for_wire_message::<Hello>()
+            .listen_for_wire_message::<Join>();
+    }
+}
+
+// A connection that completed the handshake and is ready for a player
+pub struct Handshaken {
+    pub connection_id: ConnectionId,
+    pub session: Option<SessionToken>,
+    // The account the connection logged in to
+    pub account: String,
+    // Valid, but not yet checked against the names of other players
+    pub name: String,
+}
+
+// A Join whose credentials are being checked
+struct Login {
+    task: Task<Result<(), String>>,
+    session: Option<SessionToken>,
+    account: String,
+    name: String,
+}
+
+#[derive(Default)]
+pub struct Handshakes {
+    awaiting: HashMap<ConnectionId, Timer>,
+    // Connections whose Hello was accepted and that may now Join
+    greeted: HashSet<ConnectionId>,
+    logging_in: HashMap<ConnectionId, Login>,
+    closing: HashMap<ConnectionId, Timer>,
+}
+
+impl Handshakes {
+    // Tells the client why it is being turned away, then closes the connection shortly after
+    pub fn reject(&mut self, net: &NetworkServer, connection_id: ConnectionId, reason: String) {
+        log::info!("Rejecting {:?}: {}", connection_id, reason);
+        self.awaiting.remove(&connection_id);
+        self.greeted.remove(&connection_id);
+        self.logging_in.remove(&connection_id);
+        wire::send(net, connection_id, &Rejected(reason));
+        self.closing
+            .insert(connection_id, Timer::new(CLOSE_DELAY, false));
+    }
+
+    // Connections that haven't joined yet
+    pub fn pending(&self) -> Vec<ConnectionId> {
+        self.awaiting.keys().copied().collect()
+    }
+
+    // Whether any rejected connection is still waiting to be closed
+    pub fn closing(&self) -> bool {
+        !self.closing.is_empty()
+    }
+}
+
+fn track_connections(
+    mut handshakes: ResMut<Handshakes>,
+    mut network_events: EventReader<ServerNetworkEvent>,
+) {
+    for event in network_events.iter() {
+        match event {
+            ServerNetworkEvent::Connected(connection_id) => {
+                log::debug!("New connection from {:?}", connection_id);
+                handshakes
+                    .awaiting
+                    .insert(*connection_id, Timer::new(HANDSHAKE_TIMEOUT, false));
+            }
+            ServerNetworkEvent::Disconnected(connection_id) => {
+                handshakes.awaiting.remove(connection_id);
+                handshakes.greeted.remove(connection_id);
+                handshakes.logging_in.remove(connection_id);
+                handshakes.closing.remove(connection_id);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn handle_hellos(
+    mut handshakes: ResMut<Handshakes>,
+    net: Res<NetworkServer>,
+    mut hellos: EventReader<Received<Hello>>,
+) {
+    for hello in hellos.iter() {
+        let connection_id = *hello.source();
+
+        if !handshakes.awaiting.contains_key(&connection_id)
+            || handshakes.greeted.contains(&connection_id)
+        {
+            log::warn!("Ignoring unexpected Hello from {:?}", connection_id);
+            continue;
+        }
+
+        if hello.version != PROTOCOL_VERSION {
+            handshakes.reject(&net, connection_id, version_mismatch(hello.version));
+            continue;
+        }
+
+        handshakes.greeted.insert(connection_id);
+    }
+}
+
+fn version_mismatch(version: u32) -> String {
+    format!(
+        "This server speaks protocol version {} but your client speaks version {}. \
+         Please use a matching client.",
+        PROTOCOL_VERSION, version
+    )
+}
+
+// Clients from before Packet only understand the old Rejected. Handshakes::reject sends the new
+// one too, which they ignore, and closes the connection.
+fn reject_legacy_hellos(
+    mut handshakes: ResMut<Handshakes>,
+    net: Res<NetworkServer>,
+    mut hellos: EventReader<NetworkData<LegacyHello>>,
+) {
+    for hello in hellos.iter() {
+        let connection_id = *hello.source();
+        let reason = version_mismatch(hello.version);
+        if let Err(err) = net.send_message(connection_id, LegacyRejected(reason.clone())) {
+            log::warn!("Could not send Rejected to {:?}: {}", connection_id, err);
+        }
+        handshakes.reject(&net, connection_id, reason);
+    }
+}
+
+fn handle_joins(
+    mut handshakes: ResMut<Handshakes>,
+    mut accounts: ResMut<Accounts>,
+    net: Res<NetworkServer>,
+    pool: Res<AsyncComputeTaskPool>,
+    mut joins: EventReader<Received<Join>>,
+) {
+    for join in joins.iter() {
+        let connection_id = *join.source();
+
+        if !handshakes.greeted.remove(&connection_id) {
+            log::warn!("Ignoring Join from {:?} before Hello", connection_id);
+            continue;
+        }
+
+        let name = match validate_name(&join.name) {
+            Ok(name) => name,
+            Err(reason) => {
+                handshakes.reject(&net, connection_id, reason);
+                continue;
+            }
+        };
+
+        accounts.refresh();
+        let login = accounts.login(&join.credentials);
+        let account = login.name.clone();
+        handshakes.logging_in.insert(
+            connection_id,
+            Login {
+                task: pool.spawn(async move { login.verify() }),
+                session: join.session,
+                account,
+                name,
+            },
+        );
+    }
+}
+
+fn finish_logins(
+    mut handshakes: ResMut<Handshakes>,
+    net: Res<NetworkServer>,
+    mut handshaken: EventWriter<Handshaken>,
+) {
+    let finished: Vec<(ConnectionId, Result<(), String>)> = handshakes
+        .logging_in
+        .iter_mut()
+        .filter_map(|(connection_id, login)| {
+            future::block_on(future::poll_once(&mut login.task))
+                .map(|verified| (*connection_id, verified))
+        })
+        .collect();
+
+    for (connection_id, verified) in finished {
+        let login = handshakes.logging_in.remove(&connection_id).unwrap();
+
+        if let Err(reason) = verified {
+            log::info!(
+                "{:?} failed to log in as {}: {}",
+                connection_id,
+                login.account,
+                reason
+            );
+            handshakes.reject(&net, connection_id, reason);
+            continue;
+        }
+
+        log::info!("{:?} logged in as {}", connection_id, login.account);
+        handshakes.awaiting.remove(&connection_id);
+        handshaken.send(Handshaken {
+            connection_id,
+            session: login.session,
+            account: login.account,
+            name: login.name,
+        });
+    }
+}
+
+fn expire_handshakes(time: Res<Time>, mut handshakes: ResMut<Handshakes>, net: Res<NetworkServer>) {
+    let delta = time.delta();
+
+    for connection_id in tick_expired(&mut handshakes.awaiting, delta) {
+        handshakes.reject(
+            &net,
+            connection_id,
+            "Timed out during handshake".to_string(),
+        );
+    }
+
+    for connection_id in tick_expired(&mut handshakes.closing, delta) {
+        handshakes.closing.remove(&connection_id);
+        if let Err(err) = net.disconnect(connection_id) {
+            log::warn!("Could not disconnect {:?}: {}", connection_id, err);
+        }
+    }
+}
+
+fn tick_expired(timers: &mut HashMap<ConnectionId, Timer>, delta: Duration) -> Vec<ConnectionId> {
+    timers
+        .iter_mut()
+        .filter_map(|(connection_id, timer)| {
+            timer.tick(delta);
+            timer.finished().then(|| *connection_id)
+        })
+        .collect()
+}
@@ -0,0 +1,124 @@
+use bevy::prelude::*;
+use bevy_spicy_networking::{ConnectionId, NetworkServer};
+use std::collections::{HashMap, HashSet};
+
+use woods_common::{Direction, EnteredView, PlayerId, Position, Snapshot};
+
+use crate::{
+    config::Config,
+    network::{DisplayName, Tick, TickMoves, TICK_STAGE},
+    wire,
+};
+
+pub struct InterestPlugin;
+
+impl Plugin for InterestPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_to_stage(TICK_STAGE, send_snapshots.system().after("apply_moves"));
+    }
+}
+
+// The players a connected player's client has been told about. Everyone uses the same view
+// radius, so this is also the set of players that can see this one.
+#[derive(Default)]
+pub struct Interest(pub HashMap<Entity, PlayerId>);
+
+// Works out who each client can see after this tick's moves and sends it one snapshot with
+// everything that changed in its view
+fn send_snapshots(
+    config: Res<Config>,
+    net: Res<NetworkServer>,
+    tick: Res<Tick>,
+    mut tick_moves: ResMut<TickMoves>,
+    mut watchers: Query<(Entity, &ConnectionId, &Position, &mut Interest)>,
+    players: Query<(Entity, &PlayerId, &Position, &Direction, &DisplayName)>,
+) {
+    let radius = config.view_radius;
+
+    // Bucket players into cells as wide as the view so each watcher only checks its neighbours
+    let cell_size = u32::from(radius) + 1;
+    let cell = |position: &Position| {
+        (
+            u32::from(position.x) / cell_size,
+            u32::from(position.y) / cell_size,
+        )
+    };
+    let mut grid: HashMap<(u32, u32), Vec<(Entity, Position)>> = HashMap::new();
+    for (player, _, position, _, _) in players.iter() {
+        grid.entry(cell(position))
+            .or_default()
+            .push((player, *position));
+    }
+
+    for (watcher, connection_id, position, mut interest) in watchers.iter_mut() {
+        let (x, y) = cell(position);
+        let visible: HashSet<Entity> = (x.saturating_sub(1)..=x + 1)
+            .flat_map(|x| (y.saturating_sub(1)..=y + 1).map(move |y| (x, y)))
+            .filter_map(|cell| grid.get(&cell))
+            .flatten()
+            .filter(|(other, other_position)| {
+                *other != watcher && within(position, other_position, radius)
+            })
+            .map(|(other, _)| *other)
+            .collect();
+
+        let mut snapshot = Snapshot {
+            tick: tick.0,
+            entered: Vec::new(),
+            moves: Vec::new(),
+            left: Vec::new(),
+        };
+
+        let gone: Vec<Entity> = interest
+            .0
+            .keys()
+            .filter(|other| !visible.contains(other))
+            .copied()
+            .collect();
+        for other in gone {
+            snapshot.left.push(interest.0.remove(&other).unwrap());
+        }
+
+        // Players coming into view are sent where they are now, which covers this tick's moves
+        for other in visible {
+            if interest.0.contains_key(&other) {
+                continue;
+            }
+            if let Ok((_, player_id, position, direction, name)) = players.get(other) {
+                snapshot.entered.push(EnteredView {
+                    player_id: *player_id,
+                    name: name.0.clone(),
+                    direction: *direction,
+                    position: *position,
+                });
+                interest.0.insert(other, *player_id);
+            }
+        }
+
+        // A client always hears about its own moves so it can reconcile
+        snapshot.moves = tick_moves
+            .0
+            .iter()
+            .filter(|(mover, update)| {
+                *mover == watcher
+                    || (interest.0.contains_key(mover)
+                        && !snapshot
+                            .entered
+                            .iter()
+                            .any(|entered| entered.player_id == update.player_id))
+            })
+            .map(|(_, update)| update.clone())
+            .collect();
+
+        if !snapshot.entered.is_empty() || !snapshot.moves.is_empty() || !snapshot.left.is_empty() {
+            wire::send(&net, *connection_id, &snapshot);
+        }
+    }
+
+    tick_moves.0.clear();
+}
+
+fn within(a: &Position, b: &Position, radius: u16) -> bool {
+    let distance = |a: u16, b: u16| if a > b { a - b } else { b - a };
+    distance(a.x, b.x) <= radius && distance(a.y, b.y) <= radius
+}
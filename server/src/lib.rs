@@ -0,0 +1,38 @@
+use bevy::prelude::*;
+
+use accounts::Accounts;
+use config::Config;
+use console::ConsolePlugin;
+use network::NetworkPlugin;
+use persistence::{PersistencePlugin, PlayerStore};
+use woods_common::Map;
+
+pub mod accounts;
+pub mod chat;
+pub mod config;
+pub mod console;
+pub mod handshake;
+pub mod interest;
+pub mod network;
+pub mod occupancy;
+pub mod persistence;
+pub mod ping;
+pub mod session;
+pub mod spawn;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod wire;
+
+// The whole server except for how it is run, so tests can step it one frame at a time
+pub fn build(config: Config, accounts: Accounts, map: Map, store: PlayerStore) -> AppBuilder {
+    let mut app = App::build();
+    app.insert_resource(config)
+        .insert_resource(map)
+        .insert_resource(store)
+        .insert_resource(accounts)
+        .add_plugins(MinimalPlugins)
+        .add_plugin(NetworkPlugin)
+        .add_plugin(PersistencePlugin)
+        .add_plugin(ConsolePlugin);
+    app
+}
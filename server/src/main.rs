@@ -1,11 +1,16 @@
 use std::time::Duration;
 
-use bevy::{app::ScheduleRunnerSettings, prelude::*};
+use bevy::app::ScheduleRunnerSettings;
 use log::LevelFilter;
-use network::NetworkPlugin;
 use simple_logger::SimpleLogger;
-
-mod network;
+use woods_common::Map;
+use woods_server::{
+    accounts,
+    accounts::Accounts,
+    config::Config,
+    console::{self, Console},
+    persistence::PlayerStore,
+};
 
 fn main() {
     SimpleLogger::new()
@@ -14,11 +19,52 @@ fn main() {
         .init()
         .unwrap();
 
-    App::build()
-        .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
-            1.0 / 60.0,
-        )))
-        .add_plugins(MinimalPlugins)
-        .add_plugin(NetworkPlugin)
-        .run();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            log::error!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(args) = &config.account_command {
+        if let Err(err) = accounts::run_command(&config.accounts, args) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let accounts = match Accounts::load(&config.accounts) {
+        Ok(accounts) => accounts,
+        Err(err) => {
+            log::error!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let map = match Map::load(&config.map) {
+        Ok(map) => map,
+        Err(err) => {
+            log::error!("Could not load map {:?}: {}", config.map, err);
+            panic!();
+        }
+    };
+
+    let store = match PlayerStore::load(&config.store) {
+        Ok(store) => store,
+        Err(err) => {
+            log::error!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut app = woods_server::build(config, accounts, map, store);
+    let console = app.world().get_resource::<Console>().unwrap().sender();
+    console::read_stdin(console);
+
+    app.insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
+        1.0 / 60.0,
+    )))
+    .run();
 }
@@ -1,36 +1,118 @@
-use bevy::prelude::*;
-use bevy_spicy_networking::{
-    AppNetworkServerMessage, ConnectionId, NetworkData, NetworkServer, ServerNetworkEvent,
-};
-use rand::{thread_rng, Rng};
-use std::collections::HashMap;
-use std::net::SocketAddr;
+use bevy::{core::FixedTimestep, prelude::*};
+use bevy_spicy_networking::{ConnectionId, NetworkServer, ServerNetworkEvent};
+use std::collections::{HashMap, HashSet};
 
+use crate::{
+    chat::{ChatHistory, ChatPlugin},
+    config::Config,
+    handshake::{HandshakePlugin, Handshaken, Handshakes},
+    interest::{Interest, InterestPlugin},
+    occupancy::Occupancy,
+    persistence::{PlayerStore, SavedPlayer},
+    ping::PingPlugin,
+    session::{Lingering, Sessions},
+    spawn::{setup_spawner, Spawner},
+    wire::{self, AppWireMessage, Received, WirePlugin},
+};
 use woods_common::{
-    Direction, MoveInput, MoveUpdate, PlayerId, PlayerLeft, Position, Welcome, SERVER_PORT,
+    Direction, Map, MoveInput, MoveUpdate, PlayerId, PlayerLeft, Position, SessionToken, Speed,
+    Welcome, WireMessage, MAX_NAME_LENGTH, TICK_RATE,
 };
 
+pub const TICK_STAGE: &str = "tick";
+
 pub struct NetworkPlugin;
 
 impl Plugin for NetworkPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_plugin(bevy_spicy_networking::ServerPlugin)
+            .add_plugin(WirePlugin)
+            .add_plugin(HandshakePlugin)
+            .add_plugin(ChatPlugin)
+            .add_plugin(PingPlugin)
+            .add_stage_after(
+                CoreStage::Update,
+                TICK_STAGE,
+                SystemStage::parallel()
+                    .with_run_criteria(FixedTimestep::steps_per_second(TICK_RATE)),
+            )
+            .add_plugin(InterestPlugin)
             .add_startup_system(setup_networking.system())
-            .add_system_to_stage(CoreStage::PreUpdate, handle_moves.system())
-            .add_system(handle_network_connections.system())
+            .add_startup_system(setup_spawner.system())
+            .add_system(queue_moves.system())
+            .add_system_to_stage(TICK_STAGE, apply_moves.system().label("apply_moves"))
+            .add_system(spawn_players.system().after("handshake"))
             .add_system(handle_disconnects.system())
+            .add_system(expire_lingering_players.system())
             .insert_resource(Players::default())
-            .listen_for_server_message::<MoveInput>();
+            .insert_resource(Sessions::default())
+            .insert_resource(Occupancy::default())
+            .init_resource::<Tick>()
+            .init_resource::<InputQueue>()
+            .init_resource::<TickMoves>()
+            .listen_for_wire_message::<MoveInput>();
     }
 }
 
 #[derive(Default)]
-struct Players(pub HashMap<ConnectionId, Entity>);
+pub struct Players(pub HashMap<ConnectionId, Entity>);
+
+impl Players {
+    // Unlike NetworkServer::broadcast, skips connections that are still in the handshake
+    pub fn broadcast<T: WireMessage>(&self, net: &NetworkServer, message: &T) {
+        for connection_id in self.0.keys() {
+            wire::send(net, *connection_id, message);
+        }
+    }
+}
+
+// Number of the current simulation tick
+#[derive(Default)]
+pub struct Tick(pub u32);
+
+#[derive(Default)]
+struct InputQueue(Vec<(ConnectionId, MoveInput)>);
 
-fn setup_networking(mut net: ResMut<NetworkServer>) {
-    let ip_address = "127.0.0.1".parse().unwrap();
+// Moves applied this tick, waiting to go out in snapshots
+#[derive(Default)]
+pub struct TickMoves(pub Vec<(Entity, MoveUpdate)>);
+
+// Unique among players in the world, including those waiting to resume their session
+pub struct DisplayName(pub String);
+
+// The account a player logged in to, and so who it is: an account only ever has one player, and
+// is what the player store saves it under
+pub struct AccountName(pub String);
 
-    let socket_address = SocketAddr::new(ip_address, SERVER_PORT);
+// Sequence of the last MoveInput applied for a player, echoed back in every MoveUpdate
+#[derive(Default, Clone, Copy)]
+pub struct InputSequence(pub u32);
+
+// How early a step may come, in ticks. Inputs only take effect on tick boundaries and steps
+// sent evenly don't arrive that way, so without it ordinary jitter would refuse steps.
+const STEP_SLACK_TICKS: f64 = 2.0;
+
+// The tick a player's next step is due, so steps are held to their speed. An early step borrows
+// against the ones after it, so the slack can't be used to walk faster on average; time spent
+// standing still isn't saved up.
+#[derive(Default)]
+struct StepClock(Option<f64>);
+
+impl StepClock {
+    fn ready(&self, tick: u32) -> bool {
+        self.0
+            .map_or(true, |due| f64::from(tick) + STEP_SLACK_TICKS >= due)
+    }
+
+    fn step(&mut self, tick: u32, speed: Speed) {
+        let now = f64::from(tick);
+        let from = self.0.map_or(now, |due| due.max(now));
+        self.0 = Some(from + speed.step_duration().as_secs_f64() * TICK_RATE);
+    }
+}
+
+fn setup_networking(mut net: ResMut<NetworkServer>, config: Res<Config>) {
+    let socket_address = config.listen;
 
     match net.listen(socket_address) {
         Ok(_) => (),
@@ -43,79 +125,155 @@ fn setup_networking(mut net: ResMut<NetworkServer>) {
     log::info!("Listening on {:?}", socket_address);
 }
 
-fn random_position() -> Position {
-    let mut rng = thread_rng();
-    let x: u16 = rng.gen_range(0..8);
-    let y: u16 = rng.gen_range(0..8);
-
-    Position { x: 0, y: 0 }
-}
-
-fn handle_network_connections(
+fn spawn_players(
     mut commands: Commands,
-    mut network_events: EventReader<ServerNetworkEvent>,
+    mut handshaken: EventReader<Handshaken>,
+    mut handshakes: ResMut<Handshakes>,
     mut players: ResMut<Players>,
+    mut occupancy: ResMut<Occupancy>,
+    mut spawner: ResMut<Spawner>,
+    mut sessions: ResMut<Sessions>,
+    store: Res<PlayerStore>,
+    map: Res<Map>,
     net: Res<NetworkServer>,
-    query: Query<(&Position, &Direction, &PlayerId, &ConnectionId)>,
+    playing_query: Query<&AccountName, With<ConnectionId>>,
+    lingering_query: Query<(
+        Entity,
+        &Lingering,
+        &PlayerId,
+        &Position,
+        &Direction,
+        &AccountName,
+        &DisplayName,
+    )>,
+    name_query: Query<&DisplayName>,
+    session_query: Query<&SessionToken>,
     mut next_player_id: Local<u32>,
 ) {
-    for event in network_events.iter() {
-        if let ServerNetworkEvent::Connected(connection_id) = event {
-            log::debug!("New connection from {:?}", connection_id);
-            let player = commands.spawn().id();
-            players.0.insert(*connection_id, player);
-            *next_player_id += 1;
-            let player_id = PlayerId(*next_player_id);
-            let direction: Direction = Default::default();
-            let position = random_position();
-            commands
-                .entity(player)
-                .insert(player_id)
-                .insert(*connection_id)
-                .insert(direction)
-                .insert(position);
-
-            log::debug!("Hello {:?} @ {:?}", player_id, position);
-
-            net.send_message(*connection_id, Welcome(player_id, position))
-                .unwrap();
-
-            for (
-                other_player_position,
-                other_player_direction,
-                other_player_id,
-                other_player_connection_id,
-            ) in query.iter()
-            {
-                if *other_player_id == player_id {
-                    continue;
-                }
+    // Players spawned by this system are not visible to queries until the commands are applied,
+    // so their accounts and names are kept here
+    let mut joined: Vec<(String, String)> = Vec::new();
 
-                // Send new player position to all other players
-                net.send_message(
-                    *other_player_connection_id,
-                    MoveUpdate {
-                        player_id,
-                        direction,
+    for Handshaken {
+        connection_id,
+        session,
+        account,
+        name,
+    } in handshaken.iter()
+    {
+        // An account can only have one player in the world
+        let playing = playing_query.iter().any(|playing| playing.0 == *account)
+            || joined.iter().any(|(joined, _)| joined == account);
+        if playing {
+            handshakes.reject(
+                &net,
+                *connection_id,
+                "This account is already playing".to_string(),
+            );
+            continue;
+        }
+
+        // The session token finds the player left behind by a dropped connection; failing that
+        // the account's own player is picked up again, as after a client restart. Players whose
+        // time has run out, such as kicked ones, are about to be removed.
+        let resumed = session
+            .and_then(|session| sessions.0.get(&session).copied())
+            .and_then(|player| lingering_query.get(player).ok())
+            .filter(|(_, lingering, ..)| !lingering.timer.finished())
+            .filter(|(.., lingering_account, _)| lingering_account.0 == *account)
+            .or_else(|| {
+                lingering_query
+                    .iter()
+                    .find(|(_, lingering, .., lingering_account, _)| {
+                        !lingering.timer.finished() && lingering_account.0 == *account
+                    })
+            })
+            .map(|(player, _, player_id, position, direction, _, name)| {
+                (player, *player_id, *position, *direction, name.0.clone())
+            });
+
+        // A resumed player keeps the name everyone already knows it by
+        let (player, session, player_id, position, direction, name) = match resumed {
+            Some((player, player_id, position, direction, name)) => {
+                log::info!("{:?} ({}) resumed @ {:?}", player_id, name, position);
+                commands.entity(player).remove::<Lingering>();
+                let session = match session_query.get(player) {
+                    Ok(session) => *session,
+                    Err(_) => sessions.issue(player),
+                };
+                (player, session, player_id, position, direction, name)
+            }
+            None => {
+                // Players come back where they were last saved, unless the spot is taken
+                let saved = store.get(account).filter(|saved| {
+                    map.is_walkable(&saved.position) && occupancy.is_free(&saved.position)
+                });
+                let (position, direction) = match saved {
+                    Some(SavedPlayer {
                         position,
-                        distance: 0,
-                    },
-                )
-                .unwrap();
-
-                // Send positions of all previously connected players to new player
-                net.send_message(
-                    *connection_id,
-                    MoveUpdate {
-                        player_id: *other_player_id,
-                        direction: *other_player_direction,
-                        position: *other_player_position,
-                        distance: 0,
+                        direction,
+                    }) => (position, direction),
+                    None => match spawner.choose(&map, &occupancy) {
+                        Some(position) => (position, Default::default()),
+                        None => {
+                            handshakes.reject(
+                                &net,
+                                *connection_id,
+                                "There is no room left in the woods; try again later.".to_string(),
+                            );
+                            continue;
+                        }
                     },
-                )
-                .unwrap();
+                };
+
+                let player = commands.spawn().id();
+                *next_player_id += 1;
+                let player_id = PlayerId(*next_player_id);
+                let session = sessions.issue(player);
+                let name = unique_name(
+                    name,
+                    name_query
+                        .iter()
+                        .map(|other| other.0.as_str())
+                        .chain(joined.iter().map(|(_, other)| other.as_str())),
+                );
+                occupancy.insert(player, position);
+                commands
+                    .entity(player)
+                    .insert(player_id)
+                    .insert(direction)
+                    .insert(position)
+                    .insert(session)
+                    .insert(DisplayName(name.clone()))
+                    .insert(AccountName(account.clone()))
+                    .insert(InputSequence::default())
+                    .insert(StepClock::default())
+                    .insert(ChatHistory::default());
+
+                log::debug!("Hello {:?} ({}) @ {:?}", player_id, name, position);
+                (player, session, player_id, position, direction, name)
             }
-        }
+        };
+
+        // Who it can see, and who can see it, is worked out by update_interest
+        players.0.insert(*connection_id, player);
+        commands
+            .entity(player)
+            .insert(*connection_id)
+            .insert(Interest::default());
+
+        wire::send(
+            &net,
+            *connection_id,
+            &Welcome {
+                player_id,
+                position,
+                direction,
+                session,
+                name: name.clone(),
+            },
+        );
+        joined.push((account.clone(), name));
     }
 }
 
@@ -124,58 +282,151 @@ fn handle_disconnects(
     mut network_events: EventReader<ServerNetworkEvent>,
     query: Query<&PlayerId>,
     mut commands: Commands,
-    net: Res<NetworkServer>,
 ) {
     for event in network_events.iter() {
         if let ServerNetworkEvent::Disconnected(connection_id) = event {
             if let Some(player) = players.0.remove(connection_id) {
                 match query.get(player) {
                     Ok(player_id) => {
-                        log::info!("{:?} disconnected.", player_id);
-                        net.broadcast(PlayerLeft(*player_id));
+                        log::info!("{:?} disconnected; holding its session.", player_id);
                     }
                     Err(_) => {
                         log::warn!("Disconnect for player without PlayerId {:?}", connection_id);
                     }
                 }
-                commands.entity(player).despawn();
+                // Leave the player in the world in case its client reconnects
+                commands
+                    .entity(player)
+                    .remove::<ConnectionId>()
+                    .remove::<Interest>()
+                    .insert(Lingering::default());
             } else {
-                log::warn!(
-                    "Disconnect for connection missing from connections {:?}",
-                    connection_id
-                );
+                log::debug!("{:?} disconnected before joining", connection_id);
             }
         }
     }
 }
 
-fn handle_moves(
-    players: Res<Players>,
-    net: Res<NetworkServer>,
-    mut move_inputs: EventReader<NetworkData<MoveInput>>,
-    mut query: Query<(&Position, &Direction, &PlayerId)>,
+fn expire_lingering_players(
     mut commands: Commands,
+    time: Res<Time>,
+    net: Res<NetworkServer>,
+    mut occupancy: ResMut<Occupancy>,
+    mut sessions: ResMut<Sessions>,
+    mut query: Query<(Entity, &mut Lingering, &PlayerId, &Position, &SessionToken)>,
+    mut watchers: Query<(&ConnectionId, &mut Interest)>,
 ) {
-    for move_input in move_inputs.iter() {
-        let MoveInput(direction, position) = **move_input;
+    for (player, mut lingering, player_id, position, session) in query.iter_mut() {
+        if !lingering.timer.tick(time.delta()).finished() {
+            continue;
+        }
 
-        let player = players
+        log::info!("{:?} did not come back; removing it.", player_id);
+        occupancy.remove(player, position);
+        sessions.0.remove(session);
+        for (connection_id, mut interest) in watchers.iter_mut() {
+            if interest.0.remove(&player).is_some() {
+                wire::send(&net, *connection_id, &PlayerLeft(*player_id));
+            }
+        }
+        commands.entity(player).despawn();
+    }
+}
+
+// Inputs arrive every frame but are only applied on the next tick
+fn queue_moves(
+    mut input_queue: ResMut<InputQueue>,
+    mut move_inputs: EventReader<Received<MoveInput>>,
+) {
+    for move_input in move_inputs.iter() {
+        input_queue
             .0
-            .get(&move_input.source())
-            .expect("No player associated with connection");
+            .push((*move_input.source(), (**move_input).clone()));
+    }
+}
+
+fn apply_moves(
+    map: Res<Map>,
+    players: Res<Players>,
+    mut tick: ResMut<Tick>,
+    mut occupancy: ResMut<Occupancy>,
+    mut input_queue: ResMut<InputQueue>,
+    mut tick_moves: ResMut<TickMoves>,
+    mut query: Query<(
+        &mut Position,
+        &mut Direction,
+        &mut InputSequence,
+        &mut StepClock,
+        &PlayerId,
+    )>,
+) {
+    tick.0 = tick.0.wrapping_add(1);
+
+    // In arrival order, across all connections
+    for (connection_id, move_input) in input_queue.0.drain(..) {
+        let MoveInput {
+            sequence,
+            direction,
+            position: claimed_position,
+            speed,
+        } = move_input;
 
-        if let Ok((_current_position, current_direction, player_id)) = query.get_mut(*player) {
+        let player = match players.0.get(&connection_id) {
+            Some(player) => player,
+            None => {
+                log::warn!("Ignoring Move from {:?} without a player", connection_id);
+                continue;
+            }
+        };
+
+        if let Ok((
+            mut position,
+            mut current_direction,
+            mut input_sequence,
+            mut step_clock,
+            player_id,
+        )) = query.get_mut(*player)
+        {
             let distance: u16;
+            input_sequence.0 = sequence;
 
             if *current_direction != direction {
                 // Player is just turning
-                commands.entity(*player).insert(direction);
+                *current_direction = direction;
+                distance = 0;
+            } else if !step_clock.ready(tick.0) {
+                log::debug!("{:?} stepped too soon for {:?}", player_id, speed);
                 distance = 0;
             } else {
-                // TODO: validate new position is adjacent to existing position
-                // TODO: collision detection
-                commands.entity(*player).insert(position);
-                distance = 1;
+                // The destination is always computed from the stored position so a client
+                // can never move more than one tile per step. Moves are applied in arrival
+                // order and the grid is updated immediately, so when two players step into
+                // the same tile in one tick the first one wins and the second reconciles.
+                match map.step(&position, direction) {
+                    Some(destination) if occupancy.move_player(*player, &position, destination) => {
+                        *position = destination;
+                        step_clock.step(tick.0, speed);
+                        distance = 1;
+                    }
+                    Some(destination) => {
+                        log::trace!("{:?} blocked at {:?}", player_id, destination);
+                        distance = 0;
+                    }
+                    None => {
+                        distance = 0;
+                    }
+                }
+            }
+
+            if claimed_position != *position {
+                // The MoveUpdate below carries the sequence, letting the client rewind to it
+                log::debug!(
+                    "{:?} predicted {:?} but is at {:?} after input {}",
+                    player_id,
+                    claimed_position,
+                    *position,
+                    sequence
+                );
             }
 
             log::trace!(
@@ -183,16 +434,39 @@ fn handle_moves(
                 player_id,
                 direction,
                 distance,
-                position
+                *position
             );
-            net.broadcast(MoveUpdate {
-                player_id: *player_id,
-                direction,
-                position,
-                distance,
-            })
+            tick_moves.0.push((
+                *player,
+                MoveUpdate {
+                    player_id: *player_id,
+                    direction,
+                    position: *position,
+                    distance,
+                    speed,
+                    sequence,
+                },
+            ));
         } else {
             log::warn!("Ignoring Move for player without direction/position");
         }
     }
 }
+
+// Appends a number to names that are already taken, ignoring case
+fn unique_name<'a>(name: &str, taken: impl Iterator<Item = &'a str>) -> String {
+    let taken: HashSet<String> = taken.map(|taken| taken.to_lowercase()).collect();
+
+    (1..)
+        .map(|n| match n {
+            1 => name.to_string(),
+            n => {
+                // Keep the suffix within the length limit
+                let suffix = format!(" {}", n);
+                let keep = MAX_NAME_LENGTH.saturating_sub(suffix.len());
+                format!("{}{}", name.chars().take(keep).collect::<String>(), suffix)
+            }
+        })
+        .find(|candidate| !taken.contains(&candidate.to_lowercase()))
+        .unwrap()
+}
@@ -0,0 +1,36 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+use woods_common::Position;
+
+// Authoritative record of which tiles are taken by players; solid tiles are owned by the Map
+#[derive(Default)]
+pub struct Occupancy {
+    players: HashMap<Position, Entity>,
+}
+
+impl Occupancy {
+    pub fn is_free(&self, position: &Position) -> bool {
+        !self.players.contains_key(position)
+    }
+
+    pub fn insert(&mut self, player: Entity, position: Position) {
+        self.players.insert(position, player);
+    }
+
+    pub fn remove(&mut self, player: Entity, position: &Position) {
+        if self.players.get(position) == Some(&player) {
+            self.players.remove(position);
+        }
+    }
+
+    // Returns false (and leaves the grid untouched) if the destination is taken
+    pub fn move_player(&mut self, player: Entity, from: &Position, to: Position) -> bool {
+        if !self.is_free(&to) {
+            return false;
+        }
+        self.remove(player, from);
+        self.insert(player, to);
+        true
+    }
+}
@@ -0,0 +1,143 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use woods_common::{Direction, Position};
+
+use crate::{network::AccountName, session::Lingering};
+
+// Players that are still connected are saved this often, so a crash loses little
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+pub struct PersistencePlugin;
+
+impl Plugin for PersistencePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(SaveTimer(Timer::new(SAVE_INTERVAL, true)))
+            .add_system(save_disconnected_players.system())
+            .add_system(save_periodically.system());
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SavedPlayer {
+    pub position: Position,
+    pub direction: Direction,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct StoreFile {
+    players: HashMap<String, SavedPlayer>,
+}
+
+// Flat file of where every player was last seen, by account name
+pub struct PlayerStore {
+    path: PathBuf,
+    players: HashMap<String, SavedPlayer>,
+    dirty: bool,
+}
+
+impl PlayerStore {
+    // A missing file is an empty store; it is created on the first save
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let players = match fs::read_to_string(path) {
+            Ok(text) => {
+                let file: StoreFile = toml::from_str(&text)
+                    .map_err(|err| format!("Invalid player store {:?}: {}", path, err))?;
+                file.players
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(format!("Could not read player store {:?}: {}", path, err)),
+        };
+
+        log::info!("Loaded {} saved players from {:?}", players.len(), path);
+        Ok(Self {
+            path: path.to_path_buf(),
+            players,
+            dirty: false,
+        })
+    }
+
+    pub fn get(&self, account: &str) -> Option<SavedPlayer> {
+        self.players.get(&key(account)).copied()
+    }
+
+    pub fn update(&mut self, account: &str, saved: SavedPlayer) {
+        if self.players.insert(key(account), saved) != Some(saved) {
+            self.dirty = true;
+        }
+    }
+
+    // Writes to a temporary file first so a crash mid-save can't corrupt the store
+    pub fn save(&mut self) {
+        if !self.dirty {
+            return;
+        }
+
+        let file = StoreFile {
+            players: self.players.clone(),
+        };
+        let result = toml::to_string(&file)
+            .map_err(|err| err.to_string())
+            .and_then(|text| {
+                let temporary = self.path.with_extension("tmp");
+                fs::write(&temporary, text)
+                    .and_then(|_| fs::rename(&temporary, &self.path))
+                    .map_err(|err| err.to_string())
+            });
+
+        match result {
+            Ok(()) => self.dirty = false,
+            Err(err) => log::error!("Could not save players to {:?}: {}", self.path, err),
+        }
+    }
+}
+
+// Accounts are looked up ignoring case, so their players are too
+fn key(account: &str) -> String {
+    account.to_lowercase()
+}
+
+struct SaveTimer(Timer);
+
+// Records where each of `players` is and writes the store
+pub fn save_players<'a>(
+    store: &mut PlayerStore,
+    players: impl Iterator<Item = (&'a AccountName, &'a Position, &'a Direction)>,
+) {
+    for (AccountName(account), position, direction) in players {
+        store.update(
+            account,
+            SavedPlayer {
+                position: *position,
+                direction: *direction,
+            },
+        );
+    }
+    store.save();
+}
+
+fn save_disconnected_players(
+    mut store: ResMut<PlayerStore>,
+    query: Query<(&AccountName, &Position, &Direction), Added<Lingering>>,
+) {
+    save_players(&mut store, query.iter());
+}
+
+fn save_periodically(
+    time: Res<Time>,
+    mut timer: ResMut<SaveTimer>,
+    mut store: ResMut<PlayerStore>,
+    query: Query<(&AccountName, &Position, &Direction)>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    save_players(&mut store, query.iter());
+}
@@ -0,0 +1,26 @@
+use bevy::prelude::*;
+use bevy_spicy_networking::NetworkServer;
+
+use woods_common::{Ping, Pong};
+
+use crate::wire::{self, AppWireMessage, Received};
+
+// Answers Pings so clients can measure their round trip, and logs what they measured
+pub struct PingPlugin;
+
+impl Plugin for PingPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(handle_pings.system())
+            .listen_for_wire_message::<Ping>();
+    }
+}
+
+fn handle_pings(net: Res<NetworkServer>, mut pings: EventReader<Received<Ping>>) {
+    for ping in pings.iter() {
+        wire::send(&net, *ping.source(), &Pong(ping.sequence));
+
+        if let Some(rtt) = ping.rtt {
+            log::debug!("{:?} round trip {} ms", ping.source(), rtt);
+        }
+    }
+}
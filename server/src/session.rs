@@ -0,0 +1,44 @@
+use bevy::prelude::*;
+use std::{collections::HashMap, time::Duration};
+
+use woods_common::SessionToken;
+
+// How long a player whose connection dropped stays in the world waiting for its client to return
+pub const GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+pub struct Sessions(pub HashMap<SessionToken, Entity>);
+
+impl Sessions {
+    pub fn issue(&mut self, player: Entity) -> SessionToken {
+        loop {
+            let token = SessionToken(rand::random());
+            if !self.0.contains_key(&token) {
+                self.0.insert(token, player);
+                return token;
+            }
+        }
+    }
+}
+
+// Marks a player without a connection; it is despawned once the timer finishes
+pub struct Lingering {
+    pub timer: Timer,
+}
+
+impl Default for Lingering {
+    fn default() -> Self {
+        Self {
+            timer: Timer::new(GRACE_PERIOD, false),
+        }
+    }
+}
+
+impl Lingering {
+    // Already run out, so the player is removed on the next frame and can't be resumed before
+    pub fn expired() -> Self {
+        let mut timer = Timer::new(GRACE_PERIOD, false);
+        timer.tick(GRACE_PERIOD);
+        Self { timer }
+    }
+}
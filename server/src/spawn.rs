@@ -0,0 +1,99 @@
+use bevy::prelude::*;
+use rand::{seq::SliceRandom, thread_rng};
+use std::{collections::HashSet, str::FromStr};
+
+use woods_common::{Map, Position};
+
+use crate::{config::Config, occupancy::Occupancy};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SpawnPolicy {
+    // Any free spawn point
+    Random,
+    // Spawn points in turn, skipping occupied ones
+    RoundRobin,
+}
+
+impl Default for SpawnPolicy {
+    fn default() -> Self {
+        SpawnPolicy::Random
+    }
+}
+
+impl FromStr for SpawnPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(SpawnPolicy::Random),
+            "round-robin" => Ok(SpawnPolicy::RoundRobin),
+            _ => Err(format!(
+                "unknown spawn policy {:?}; expected random or round-robin",
+                s
+            )),
+        }
+    }
+}
+
+// Places players the store has no saved position for; everyone else comes back where they were
+pub struct Spawner {
+    policy: SpawnPolicy,
+    points: Vec<Position>,
+    next: usize,
+}
+
+impl Spawner {
+    pub fn new(policy: SpawnPolicy, map: &Map) -> Self {
+        let mut points: Vec<Position> = map
+            .spawn_zones
+            .iter()
+            .flat_map(|zone| zone.positions())
+            .filter(|position| map.is_walkable(position))
+            .collect();
+
+        if points.is_empty() {
+            log::warn!("Map has no spawn points; players may spawn on any walkable tile");
+            points = (0..map.width as usize * map.height as usize)
+                .map(|index| map.position(index))
+                .filter(|position| map.is_walkable(position))
+                .collect();
+        }
+
+        // Overlapping zones would otherwise weight their shared tiles
+        let mut seen = HashSet::new();
+        points.retain(|position| seen.insert(*position));
+
+        Self {
+            policy,
+            points,
+            next: 0,
+        }
+    }
+
+    // Picks a walkable spawn point no other player is standing on
+    pub fn choose(&mut self, map: &Map, occupancy: &Occupancy) -> Option<Position> {
+        let is_safe =
+            |position: &Position| map.is_walkable(position) && occupancy.is_free(position);
+
+        match self.policy {
+            SpawnPolicy::Random => self.choose_random(is_safe),
+            SpawnPolicy::RoundRobin => {
+                let count = self.points.len();
+                let offset = (0..count).find(|i| is_safe(&self.points[(self.next + i) % count]))?;
+                let index = (self.next + offset) % count;
+                self.next = index + 1;
+                Some(self.points[index])
+            }
+        }
+    }
+
+    fn choose_random(&self, is_safe: impl Fn(&Position) -> bool) -> Option<Position> {
+        let free: Vec<&Position> = self.points.iter().filter(|p| is_safe(p)).collect();
+        free.choose(&mut thread_rng()).map(|position| **position)
+    }
+}
+
+pub fn setup_spawner(mut commands: Commands, map: Res<Map>, config: Res<Config>) {
+    log::info!("Spawning players with the {:?} policy", config.spawn_policy);
+    commands.insert_resource(Spawner::new(config.spawn_policy, &map));
+}
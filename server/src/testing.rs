@@ -0,0 +1,125 @@
+// Starts a server for the server's and the client's integration tests, so both set it up the
+// same way. Only built with the testing feature.
+
+use bevy::{app::ManualEventReader, prelude::*};
+use bevy_spicy_networking::{NetworkServer, ServerNetworkEvent};
+use std::{
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+use woods_common::Map;
+
+use crate::{
+    accounts::Accounts,
+    config::{default_map_path, Config},
+    persistence::PlayerStore,
+    spawn::SpawnPolicy,
+};
+
+// Every test account has this password
+pub const PASSWORD: &str = "correct horse battery staple";
+
+// How long to wait for the server to start listening on a port before trying another
+const LISTEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct TestServer {
+    pub app: App,
+    pub address: SocketAddr,
+    // Holds the accounts and player store until the test ends
+    pub dir: TempDir,
+}
+
+impl TestServer {
+    // Starts a server with an account for each name
+    pub fn new(names: &[&str]) -> Self {
+        Self::with_config(names, |_| {})
+    }
+
+    pub fn with_config(names: &[&str], configure: impl FnOnce(&mut Config)) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config {
+            listen: free_address(),
+            map: default_map_path(),
+            spawn_policy: SpawnPolicy::Random,
+            store: dir.path().join("players.toml"),
+            accounts: dir.path().join("accounts.toml"),
+            view_radius: 16,
+            console: None,
+            account_command: None,
+        };
+        configure(&mut config);
+
+        let mut accounts = Accounts::load(&config.accounts).unwrap();
+        for name in names {
+            accounts.add(name, PASSWORD).unwrap();
+        }
+        accounts.save().unwrap();
+
+        let map = Map::load(Path::new(&config.map)).unwrap();
+        let players = PlayerStore::load(&config.store).unwrap();
+        let mut address = config.listen;
+        let mut app = crate::build(config, accounts, map, players).app;
+        // Runs the startup systems, which start listening
+        app.update();
+
+        while !listening(&mut app, address) {
+            address = free_address();
+            log::warn!("Port was taken; trying {:?} instead", address);
+            app.world.get_resource_mut::<Config>().unwrap().listen = address;
+            app.world
+                .get_resource_mut::<NetworkServer>()
+                .unwrap()
+                .listen(address)
+                .unwrap();
+        }
+
+        TestServer { app, address, dir }
+    }
+}
+
+// Only says yes once the server has accepted a connection of its own on the address, since
+// something else may have bound the port first. The probe connection is dropped again, which the
+// server treats like any client leaving during the handshake.
+fn listening(app: &mut App, address: SocketAddr) -> bool {
+    let mut reader = ManualEventReader::<ServerNetworkEvent>::default();
+    let mut probe = None;
+    let start = Instant::now();
+    while start.elapsed() < LISTEN_TIMEOUT {
+        if probe.is_none() {
+            probe = TcpStream::connect(address).ok();
+        }
+        app.update();
+
+        let events = app
+            .world
+            .get_resource::<Events<ServerNetworkEvent>>()
+            .unwrap();
+        for event in reader.iter(events) {
+            match event {
+                ServerNetworkEvent::Connected(_) => return true,
+                ServerNetworkEvent::Error(err) => {
+                    log::warn!("Could not listen on {:?}: {}", address, err);
+                    return false;
+                }
+                _ => {}
+            }
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    panic!(
+        "Timed out waiting for the server to listen on {:?}",
+        address
+    );
+}
+
+// The OS hands out a port that is free right now, but it is released again for the server to
+// take; `listening` catches anyone else getting there first
+fn free_address() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .unwrap()
+}
@@ -0,0 +1,73 @@
+use bevy::prelude::*;
+use bevy_spicy_networking::{AppNetworkServerMessage, ConnectionId, NetworkData, NetworkServer};
+use std::ops::Deref;
+
+use woods_common::{
+    wire::{self, DECODE_STAGE},
+    Packet, WireMessage,
+};
+
+pub struct WirePlugin;
+
+impl Plugin for WirePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_stage_after(CoreStage::PreUpdate, DECODE_STAGE, SystemStage::parallel())
+            .listen_for_server_message::<Packet>();
+    }
+}
+
+// A decoded message from a client, read through EventReader<Received<T>>
+pub struct Received<T> {
+    source: ConnectionId,
+    message: T,
+}
+
+impl<T> Received<T> {
+    pub fn source(&self) -> &ConnectionId {
+        &self.source
+    }
+}
+
+impl<T> Deref for Received<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.message
+    }
+}
+
+pub trait AppWireMessage {
+    fn listen_for_wire_message<T: WireMessage>(&mut self) -> &mut Self;
+}
+
+impl AppWireMessage for AppBuilder {
+    fn listen_for_wire_message<T: WireMessage>(&mut self) -> &mut Self {
+        self.add_event::<Received<T>>()
+            .add_system_to_stage(DECODE_STAGE, decode_packets::<T>.system())
+    }
+}
+
+fn decode_packets<T: WireMessage>(
+    mut packets: EventReader<NetworkData<Packet>>,
+    mut received: EventWriter<Received<T>>,
+) {
+    for packet in packets.iter() {
+        if wire::message_id(packet) != Some(T::ID) {
+            continue;
+        }
+
+        match wire::decode::<T>(packet) {
+            Ok(message) => received.send(Received {
+                source: *packet.source(),
+                message,
+            }),
+            Err(err) => log::warn!("Malformed {} from {:?}: {}", T::NAME, packet.source(), err),
+        }
+    }
+}
+
+pub fn send<T: WireMessage>(net: &NetworkServer, connection_id: ConnectionId, message: &T) {
+    if let Err(err) = net.send_message(connection_id, wire::encode(message)) {
+        log::warn!("Could not send {} to {:?}: {}", T::NAME, connection_id, err);
+    }
+}
@@ -0,0 +1,243 @@
+// Runs a server and any number of headless clients in the test process. Everything is stepped
+// by hand from the test thread, so nothing needs a display or a runner.
+
+use bevy::prelude::*;
+use bevy_spicy_networking::{
+    AppNetworkClientMessage, ClientNetworkEvent, NetworkClient, NetworkData, NetworkSettings,
+};
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+use woods_common::{
+    wire::{self, LegacyRejected},
+    Credentials, Hello, Join, Packet, Welcome, WireMessage, PROTOCOL_VERSION,
+};
+use woods_server::{
+    config::Config,
+    console::{Console, ConsoleLine},
+    session::Lingering,
+    testing::TestServer,
+};
+
+pub use woods_server::testing::PASSWORD;
+
+// How long to wait for something to happen before failing the test
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Harness {
+    pub server: App,
+    address: SocketAddr,
+    clients: Vec<Option<App>>,
+    // Holds the accounts and player store until the test ends
+    _dir: TempDir,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Client(usize);
+
+// Everything a client received that the test has not taken yet, oldest first
+#[derive(Default)]
+struct Inbox(Vec<Packet>);
+
+#[derive(Default)]
+struct Connected(bool);
+
+impl Harness {
+    // Starts a server with an account for each name
+    pub fn new(names: &[&str]) -> Self {
+        Self::with_config(names, |_| {})
+    }
+
+    pub fn with_config(names: &[&str], configure: impl FnOnce(&mut Config)) -> Self {
+        let TestServer { app, address, dir } = TestServer::with_config(names, configure);
+        Harness {
+            server: app,
+            address,
+            clients: Vec::new(),
+            _dir: dir,
+        }
+    }
+
+    // Opens a connection without saying anything on it
+    pub fn connect(&mut self) -> Client {
+        let mut builder = App::build();
+        builder
+            .add_plugins(MinimalPlugins)
+            .add_plugin(bevy_spicy_networking::ClientPlugin)
+            .init_resource::<Inbox>()
+            .init_resource::<Connected>()
+            .add_system(collect.system());
+        builder
+            .listen_for_client_message::<Packet>()
+            .listen_for_client_message::<LegacyRejected>();
+
+        let mut app = builder.app;
+        app.world
+            .get_resource_mut::<NetworkClient>()
+            .unwrap()
+            .connect(self.address, NetworkSettings::default());
+
+        let client = Client(self.clients.len());
+        self.clients.push(Some(app));
+        self.run_until("the client to connect", |harness| {
+            harness
+                .client(client)
+                .world
+                .get_resource::<Connected>()
+                .unwrap()
+                .0
+        });
+        client
+    }
+
+    // Connects and logs in with the account's password, returning the server's welcome
+    pub fn join(&mut self, name: &str) -> (Client, Welcome) {
+        self.join_as(name, name)
+    }
+
+    // As join, asking to be called `name` rather than after the account
+    pub fn join_as(&mut self, account: &str, name: &str) -> (Client, Welcome) {
+        let client = self.connect();
+        self.send(
+            client,
+            &Hello {
+                version: PROTOCOL_VERSION,
+            },
+        );
+        self.send(
+            client,
+            &Join {
+                session: None,
+                credentials: Credentials::Password {
+                    username: account.to_string(),
+                    password: PASSWORD.to_string(),
+                },
+                name: name.to_string(),
+            },
+        );
+        let welcome = self.expect::<Welcome>(client);
+        (client, welcome)
+    }
+
+    // Closes the client's connection as if its process had exited
+    pub fn disconnect(&mut self, client: Client) {
+        self.clients[client.0] = None;
+    }
+
+    pub fn send<T: WireMessage>(&mut self, client: Client, message: &T) {
+        self.client(client)
+            .world
+            .get_resource::<NetworkClient>()
+            .unwrap()
+            .send_message(wire::encode(message))
+            .unwrap();
+    }
+
+    // Steps until the client receives a T, and takes it out of the client's inbox. Other
+    // messages are left for later.
+    pub fn expect<T: WireMessage>(&mut self, client: Client) -> T {
+        let mut found = None;
+        self.run_until(T::NAME, |harness| {
+            found = harness.take::<T>(client);
+            found.is_some()
+        });
+        found.unwrap()
+    }
+
+    // Takes the oldest T the client has received so far, without stepping
+    pub fn take<T: WireMessage>(&mut self, client: Client) -> Option<T> {
+        let mut inbox = self
+            .client(client)
+            .world
+            .get_resource_mut::<Inbox>()
+            .unwrap();
+        let index = inbox
+            .0
+            .iter()
+            .position(|packet| wire::message_id(packet) == Some(T::ID))?;
+        let packet = inbox.0.remove(index);
+        Some(wire::decode(&packet).unwrap())
+    }
+
+    // Types a line at the admin console and waits for the answer
+    pub fn console(&mut self, text: &str) -> String {
+        let (reply, replies) = crossbeam_channel::unbounded();
+        let console = self.server.world.get_resource::<Console>().unwrap();
+        console
+            .sender()
+            .send(ConsoleLine {
+                text: text.to_string(),
+                reply,
+            })
+            .unwrap();
+
+        let mut answer = None;
+        self.run_until("the console to answer", |_| {
+            answer = replies.try_recv().ok();
+            answer.is_some()
+        });
+        answer.unwrap()
+    }
+
+    // Ends every lingering player's grace period, as if the server had waited it out
+    pub fn expire_sessions(&mut self) {
+        let mut query = self.server.world.query::<&mut Lingering>();
+        for mut lingering in query.iter_mut(&mut self.server.world) {
+            let remaining = lingering.timer.duration();
+            lingering.timer.tick(remaining);
+        }
+    }
+
+    // Runs one frame on the server and every client
+    pub fn update(&mut self) {
+        self.server.update();
+        for client in self.clients.iter_mut().flatten() {
+            client.update();
+        }
+    }
+
+    pub fn run_until(&mut self, what: &str, mut done: impl FnMut(&mut Self) -> bool) {
+        let start = Instant::now();
+        while !done(self) {
+            if start.elapsed() > TIMEOUT {
+                panic!("Timed out waiting for {}", what);
+            }
+            self.update();
+            // Gives the networking threads a chance to move messages along
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    // Steps for a while, for checking that something does not happen
+    pub fn run_for(&mut self, duration: Duration) {
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            self.update();
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    pub fn client(&mut self, client: Client) -> &mut App {
+        self.clients[client.0]
+            .as_mut()
+            .expect("client is disconnected")
+    }
+}
+
+fn collect(
+    mut inbox: ResMut<Inbox>,
+    mut connected: ResMut<Connected>,
+    mut packets: EventReader<NetworkData<Packet>>,
+    mut network_events: EventReader<ClientNetworkEvent>,
+) {
+    for event in network_events.iter() {
+        connected.0 = matches!(event, ClientNetworkEvent::Connected);
+    }
+    inbox
+        .0
+        .extend(packets.iter().map(|packet| (**packet).clone()));
+}
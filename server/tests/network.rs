@@ -0,0 +1,562 @@
+mod harness;
+
+use bevy::app::{AppExit, Events, ManualEventReader};
+use bevy_spicy_networking::{NetworkClient, NetworkData};
+use std::time::Duration;
+
+use harness::{Client, Harness, PASSWORD};
+use woods_common::{
+    wire::{LegacyHello, LegacyRejected},
+    Chat, Credentials, Direction, Hello, Join, Map, MoveInput, MoveUpdate, Ping, PlayerId,
+    PlayerLeft, Pong, Position, Rejected, Say, Snapshot, Speed, Welcome, PROTOCOL_VERSION,
+};
+use woods_server::{accounts::Accounts, network::DisplayName, session::Lingering};
+
+// Steps until one of the client's snapshots has what `find` is looking for
+fn expect_in_snapshot<T>(
+    harness: &mut Harness,
+    client: Client,
+    mut find: impl FnMut(&Snapshot) -> Option<T>,
+) -> T {
+    loop {
+        let snapshot = harness.expect::<Snapshot>(client);
+        if let Some(found) = find(&snapshot) {
+            return found;
+        }
+    }
+}
+
+fn expect_move(harness: &mut Harness, client: Client, player_id: PlayerId) -> MoveUpdate {
+    expect_in_snapshot(harness, client, |snapshot| {
+        snapshot
+            .moves
+            .iter()
+            .find(|update| update.player_id == player_id)
+            .cloned()
+    })
+}
+
+// Every MoveUpdate about the player up to the one acknowledging `sequence`
+fn expect_moves_until(
+    harness: &mut Harness,
+    client: Client,
+    player_id: PlayerId,
+    sequence: u32,
+) -> Vec<MoveUpdate> {
+    let mut updates = Vec::new();
+    loop {
+        let snapshot = harness.expect::<Snapshot>(client);
+        for update in snapshot.moves {
+            if update.player_id == player_id {
+                let done = update.sequence == sequence;
+                updates.push(update);
+                if done {
+                    return updates;
+                }
+            }
+        }
+    }
+}
+
+fn server_position(harness: &mut Harness, player_id: PlayerId) -> Option<Position> {
+    let mut query = harness.server.world.query::<(&PlayerId, &Position)>();
+    query
+        .iter(&harness.server.world)
+        .find(|(other, _)| **other == player_id)
+        .map(|(_, position)| *position)
+}
+
+// The server notices dropped connections on its own time
+fn wait_until_lingering(harness: &mut Harness, player_id: PlayerId) {
+    harness.run_until("the player to linger", |harness| {
+        let mut query = harness.server.world.query::<(&PlayerId, &Lingering)>();
+        query
+            .iter(&harness.server.world)
+            .any(|(other, _)| *other == player_id)
+    });
+}
+
+// Everyone sees everyone, wherever they spawn
+fn wide_view() -> Harness {
+    Harness::with_config(&["alice", "bob"], |config| config.view_radius = u16::MAX)
+}
+
+#[test]
+fn welcomes_a_player_into_the_world() {
+    let mut harness = Harness::new(&["alice"]);
+    let (_, welcome) = harness.join("alice");
+
+    assert_eq!(welcome.name, "alice");
+    assert_eq!(
+        server_position(&mut harness, welcome.player_id),
+        Some(welcome.position)
+    );
+
+    let mut query = harness.server.world.query::<(&PlayerId, &DisplayName)>();
+    let (_, name) = query
+        .iter(&harness.server.world)
+        .find(|(player_id, _)| **player_id == welcome.player_id)
+        .unwrap();
+    assert_eq!(name.0, "alice");
+}
+
+#[test]
+fn keeps_display_names_unique() {
+    let mut harness = Harness::new(&["alice", "bob"]);
+    let (_, alice) = harness.join_as("alice", "Fox");
+    let (_, bob) = harness.join_as("bob", "fox");
+
+    assert_eq!(alice.name, "Fox");
+    assert_eq!(bob.name, "fox 2");
+}
+
+#[test]
+fn rejects_an_invalid_display_name() {
+    let mut harness = Harness::new(&["alice"]);
+    let client = harness.connect();
+    harness.send(
+        client,
+        &Hello {
+            version: PROTOCOL_VERSION,
+        },
+    );
+    harness.send(
+        client,
+        &Join {
+            session: None,
+            credentials: Credentials::Password {
+                username: "alice".to_string(),
+                password: PASSWORD.to_string(),
+            },
+            name: "<alice>".to_string(),
+        },
+    );
+
+    let Rejected(reason) = harness.expect::<Rejected>(client);
+    assert!(reason.contains("Names"), "{}", reason);
+}
+
+#[test]
+fn rejects_a_wrong_password() {
+    let mut harness = Harness::new(&["alice"]);
+    let client = harness.connect();
+    harness.send(
+        client,
+        &Hello {
+            version: PROTOCOL_VERSION,
+        },
+    );
+    harness.send(
+        client,
+        &Join {
+            session: None,
+            credentials: Credentials::Password {
+                username: "alice".to_string(),
+                password: format!("not {}", PASSWORD),
+            },
+            name: "alice".to_string(),
+        },
+    );
+
+    let Rejected(reason) = harness.expect::<Rejected>(client);
+    assert!(reason.contains("Invalid"), "{}", reason);
+    harness.run_for(Duration::from_millis(200));
+    assert!(harness.take::<Welcome>(client).is_none());
+}
+
+#[test]
+fn rejects_another_protocol_version() {
+    let mut harness = Harness::new(&[]);
+    let client = harness.connect();
+    harness.send(
+        client,
+        &Hello {
+            version: PROTOCOL_VERSION + 1,
+        },
+    );
+
+    let Rejected(reason) = harness.expect::<Rejected>(client);
+    assert!(reason.contains("protocol version"), "{}", reason);
+}
+
+#[test]
+fn tells_clients_from_before_packets_to_update() {
+    let mut harness = Harness::new(&[]);
+    let client = harness.connect();
+    harness
+        .client(client)
+        .world
+        .get_resource::<NetworkClient>()
+        .unwrap()
+        .send_message(LegacyHello { version: 7 })
+        .unwrap();
+
+    let mut reader = ManualEventReader::<NetworkData<LegacyRejected>>::default();
+    let mut reason = None;
+    harness.run_until("the old Rejected", |harness| {
+        let events = harness
+            .client(client)
+            .world
+            .get_resource::<Events<NetworkData<LegacyRejected>>>()
+            .unwrap();
+        reason = reader
+            .iter(events)
+            .last()
+            .map(|rejected| rejected.0.clone());
+        reason.is_some()
+    });
+    let reason = reason.unwrap();
+    assert!(reason.contains("version 7"), "{}", reason);
+}
+
+#[test]
+fn echoes_moves_to_the_mover_and_players_in_view() {
+    let mut harness = wide_view();
+    let (alice, alice_welcome) = harness.join("alice");
+    let (bob, _) = harness.join("bob");
+
+    // Bob has to know about Alice before he can hear about her moves
+    expect_in_snapshot(&mut harness, bob, |snapshot| {
+        snapshot
+            .entered
+            .iter()
+            .find(|entered| entered.player_id == alice_welcome.player_id)
+            .map(|_| ())
+    });
+
+    // Facing the same way as on arrival, so this is a step rather than a turn
+    let direction = alice_welcome.direction;
+    harness.send(
+        alice,
+        &MoveInput {
+            sequence: 1,
+            direction,
+            position: alice_welcome
+                .position
+                .step(direction)
+                .unwrap_or(alice_welcome.position),
+            speed: Speed::Walk,
+        },
+    );
+
+    let echoed = expect_move(&mut harness, alice, alice_welcome.player_id);
+    assert_eq!(echoed.sequence, 1);
+    assert_eq!(echoed.direction, direction);
+    assert_eq!(
+        server_position(&mut harness, alice_welcome.player_id),
+        Some(echoed.position)
+    );
+
+    let seen = expect_move(&mut harness, bob, alice_welcome.player_id);
+    assert_eq!(seen.position, echoed.position);
+    assert_eq!(seen.distance, echoed.distance);
+}
+
+#[test]
+fn answers_pings() {
+    let mut harness = Harness::new(&["alice"]);
+    let (alice, _) = harness.join("alice");
+
+    harness.send(
+        alice,
+        &Ping {
+            sequence: 7,
+            rtt: Some(40),
+        },
+    );
+
+    let Pong(sequence) = harness.expect::<Pong>(alice);
+    assert_eq!(sequence, 7);
+}
+
+#[test]
+fn limits_a_first_burst_of_chat() {
+    let mut harness = Harness::new(&["alice"]);
+    let (client, welcome) = harness.join("alice");
+
+    for n in 0..6 {
+        harness.send(client, &Say(format!("hello {}", n)));
+    }
+
+    for n in 0..5 {
+        let chat = harness.expect::<Chat>(client);
+        assert_eq!(chat.player_id, Some(welcome.player_id));
+        assert_eq!(chat.text, format!("hello {}", n));
+    }
+    let notice = harness.expect::<Chat>(client);
+    assert_eq!(notice.player_id, None);
+    assert!(notice.text.contains("too quickly"), "{}", notice.text);
+}
+
+#[test]
+fn tells_players_in_view_when_someone_leaves() {
+    let mut harness = wide_view();
+    let (alice, _) = harness.join("alice");
+    let (bob, bob_welcome) = harness.join("bob");
+    expect_in_snapshot(&mut harness, alice, |snapshot| {
+        snapshot
+            .entered
+            .iter()
+            .find(|entered| entered.player_id == bob_welcome.player_id)
+            .map(|_| ())
+    });
+
+    harness.disconnect(bob);
+    // Bob stays in the world for a while in case he comes back
+    wait_until_lingering(&mut harness, bob_welcome.player_id);
+    assert!(harness.take::<PlayerLeft>(alice).is_none());
+
+    harness.expire_sessions();
+    let PlayerLeft(player_id) = harness.expect::<PlayerLeft>(alice);
+    assert_eq!(player_id, bob_welcome.player_id);
+    assert_eq!(server_position(&mut harness, bob_welcome.player_id), None);
+}
+
+#[test]
+fn resumes_a_lingering_player() {
+    let mut harness = Harness::new(&["alice"]);
+    let (alice, first) = harness.join("alice");
+
+    harness.disconnect(alice);
+    wait_until_lingering(&mut harness, first.player_id);
+    let (_, second) = harness.join("alice");
+
+    assert_eq!(second.player_id, first.player_id);
+    assert_eq!(second.position, first.position);
+}
+
+#[test]
+fn holds_steps_to_their_speed() {
+    let mut harness = Harness::new(&["alice"]);
+    let (alice, welcome) = harness.join("alice");
+
+    let map = harness.server.world.get_resource::<Map>().unwrap();
+    let direction = [
+        Direction::North,
+        Direction::South,
+        Direction::East,
+        Direction::West,
+    ]
+    .iter()
+    .copied()
+    .find(|direction| map.step(&welcome.position, *direction).is_some())
+    .expect("alice spawned boxed in");
+    let first = map.step(&welcome.position, direction).unwrap();
+
+    // Turning doesn't count as a step, so only the second of the two back to back steps is early
+    let mut predicted = vec![first, first];
+    if direction != welcome.direction {
+        predicted.insert(0, welcome.position);
+    }
+    for (sequence, position) in predicted.iter().enumerate() {
+        harness.send(
+            alice,
+            &MoveInput {
+                sequence: sequence as u32 + 1,
+                direction,
+                position: *position,
+                speed: Speed::Walk,
+            },
+        );
+    }
+
+    let updates = expect_moves_until(
+        &mut harness,
+        alice,
+        welcome.player_id,
+        predicted.len() as u32,
+    );
+    let steps: Vec<_> = updates.iter().map(|update| update.distance).collect();
+    assert!(steps.ends_with(&[1, 0]), "{:?}", steps);
+    assert_eq!(updates.last().unwrap().position, first);
+    assert_eq!(
+        server_position(&mut harness, welcome.player_id),
+        Some(first)
+    );
+}
+
+#[test]
+fn accepts_steps_paced_to_their_speed() {
+    let mut harness = Harness::new(&["alice"]);
+    let (alice, welcome) = harness.join("alice");
+
+    // Four steps in a straight line, facing the way first if alice isn't already
+    let map = harness.server.world.get_resource::<Map>().unwrap();
+    let (direction, path) = [
+        Direction::North,
+        Direction::South,
+        Direction::East,
+        Direction::West,
+    ]
+    .iter()
+    .find_map(|direction| {
+        let mut path = vec![welcome.position];
+        for _ in 0..4 {
+            let next = map.step(path.last().unwrap(), *direction)?;
+            path.push(next);
+        }
+        Some((*direction, path))
+    })
+    .expect("alice spawned without room to walk");
+    let mut sequence = 0;
+    if direction != welcome.direction {
+        sequence += 1;
+        harness.send(
+            alice,
+            &MoveInput {
+                sequence,
+                direction,
+                position: welcome.position,
+                speed: Speed::Walk,
+            },
+        );
+    }
+
+    let first_step = sequence + 1;
+
+    // Sent exactly a step apart, except for one step held up on the way that arrives late and
+    // bunches up with the next
+    let step = Speed::Walk.step_duration();
+    let jitter = Duration::from_millis(60);
+    let gaps = [step, step + jitter, step - jitter, step];
+    for (position, gap) in path[1..].iter().zip(gaps.iter()) {
+        sequence += 1;
+        harness.send(
+            alice,
+            &MoveInput {
+                sequence,
+                direction,
+                position: *position,
+                speed: Speed::Walk,
+            },
+        );
+        harness.run_for(*gap);
+    }
+
+    let updates = expect_moves_until(&mut harness, alice, welcome.player_id, sequence);
+    let steps: Vec<_> = updates
+        .iter()
+        .filter(|update| update.sequence >= first_step)
+        .map(|update| update.distance)
+        .collect();
+    assert_eq!(steps, vec![1, 1, 1, 1]);
+    assert_eq!(
+        server_position(&mut harness, welcome.player_id),
+        path.last().copied()
+    );
+}
+
+#[test]
+fn lists_players_at_the_console() {
+    let mut harness = Harness::new(&["alice", "bob"]);
+    let (_, alice) = harness.join("alice");
+    harness.join("bob");
+
+    let answer = harness.console("list");
+    assert!(answer.starts_with("2 in the world"), "{}", answer);
+    assert!(
+        answer.contains(&format!(
+            "#{} alice at ({}, {})",
+            alice.player_id.0, alice.position.x, alice.position.y
+        )),
+        "{}",
+        answer
+    );
+}
+
+#[test]
+fn kicks_and_bans_from_the_console() {
+    let mut harness = Harness::new(&["alice", "bob"]);
+    let (alice, alice_welcome) = harness.join("alice");
+    let (bob, bob_welcome) = harness.join("bob");
+
+    assert_eq!(harness.console("kick ALICE"), "Kicked alice");
+    let Rejected(reason) = harness.expect::<Rejected>(alice);
+    assert!(reason.contains("Kicked"), "{}", reason);
+
+    // Kicked players are gone from the world rather than waiting to be resumed
+    harness.run_until("alice to be removed", |harness| {
+        server_position(harness, alice_welcome.player_id).is_none()
+    });
+    let (_, welcome) = harness.join("alice");
+    assert_ne!(welcome.player_id, alice_welcome.player_id);
+
+    assert_eq!(
+        harness.console(&format!("ban #{}", bob_welcome.player_id.0)),
+        "Banned bob"
+    );
+    let Rejected(reason) = harness.expect::<Rejected>(bob);
+    assert!(reason.contains("Banned"), "{}", reason);
+    let accounts = harness.server.world.get_resource::<Accounts>().unwrap();
+    let login = accounts.login(&Credentials::Password {
+        username: "bob".to_string(),
+        password: PASSWORD.to_string(),
+    });
+    assert_eq!(
+        login.verify(),
+        Err("This account has been disabled".to_string())
+    );
+}
+
+#[test]
+fn teleports_from_the_console() {
+    let mut harness = Harness::new(&["alice"]);
+    let (client, welcome) = harness.join("alice");
+
+    let map = harness.server.world.get_resource::<Map>().unwrap();
+    let (width, height) = (map.width, map.height);
+    let destination = (0..width)
+        .flat_map(|x| (0..height).map(move |y| Position { x, y }))
+        .find(|position| map.is_walkable(position) && *position != welcome.position)
+        .unwrap();
+
+    // Off the edge of the map, since the map may have no solid tiles
+    let answer = harness.console(&format!("teleport alice {} 0", width));
+    assert!(answer.contains("isn't walkable"), "{}", answer);
+
+    let answer = harness.console(&format!(
+        "teleport alice {} {}",
+        destination.x, destination.y
+    ));
+    assert!(answer.starts_with("Teleported alice"), "{}", answer);
+
+    let update = expect_move(&mut harness, client, welcome.player_id);
+    assert_eq!(update.position, destination);
+    assert_eq!(update.distance, 0);
+    assert_eq!(
+        server_position(&mut harness, welcome.player_id),
+        Some(destination)
+    );
+}
+
+#[test]
+fn shuts_down_from_the_console() {
+    let mut harness = Harness::new(&["alice"]);
+    let (client, _) = harness.join("alice");
+
+    let answer = harness.console("shutdown");
+    assert!(answer.contains("shutting down"), "{}", answer);
+
+    // Everyone is told and disconnected before the server stops
+    let mut reader = ManualEventReader::<AppExit>::default();
+    let mut exited = |harness: &mut Harness| {
+        let events = harness
+            .server
+            .world
+            .get_resource::<Events<AppExit>>()
+            .unwrap();
+        reader.iter(events).next().is_some()
+    };
+    assert!(!exited(&mut harness));
+    let Rejected(reason) = harness.expect::<Rejected>(client);
+    assert!(reason.contains("shutting down"), "{}", reason);
+    harness.run_until("the server to exit", |harness| exited(harness));
+}
+
+#[test]
+fn rejects_unknown_console_commands() {
+    let mut harness = Harness::new(&[]);
+    let answer = harness.console("fly away");
+    assert!(answer.starts_with("Unknown command"), "{}", answer);
+    assert!(harness.console("help").contains("teleport"));
+}